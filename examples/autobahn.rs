@@ -3,7 +3,7 @@ use std::str::{self, FromStr};
 use clap::Parser;
 use http::uri::Uri;
 use monoio::io::{AsyncReadRent, AsyncWriteRent, Split};
-use monoio_ws::{Client, CloseCode, Config, Opcode};
+use monoio_ws::{Client, CloseCode, CloseFrame, Config, Opcode};
 
 // Agent name reported to the test server.
 const AGENT: &str = "monoio-ws";
@@ -60,7 +60,7 @@ async fn get_case_count(uri: &Uri) -> anyhow::Result<usize> {
     let case_count = text.parse::<usize>()?;
 
     client
-        .send_close(&u16::from(CloseCode::Normal).to_be_bytes())
+        .send_close(Some(&CloseFrame::new(CloseCode::Normal, "")))
         .await?;
 
     println!("Read test count: {case_count}.");
@@ -82,7 +82,7 @@ async fn run_test_case(uri: &Uri, case: usize) -> anyhow::Result<()> {
             Ok(()) => {
                 buffer = buf;
             }
-            Err(monoio_ws::Error::ProtocolViolation(_) | monoio_ws::Error::Closed { .. }) => {
+            Err(monoio_ws::Error::ProtocolViolation(_) | monoio_ws::Error::Closed(_)) => {
                 break;
             }
             Err(e) => {
@@ -96,18 +96,19 @@ async fn run_test_case(uri: &Uri, case: usize) -> anyhow::Result<()> {
 }
 
 async fn process_message(
-    client: &mut Client<impl AsyncReadRent + AsyncWriteRent + Split>,
+    client: &mut Client<impl AsyncReadRent + AsyncWriteRent + Split + 'static>,
     buffer: Vec<u8>,
 ) -> monoio_ws::BufResult<()> {
     let (res, buffer) = client.next_msg(buffer).await;
     match res {
         Ok(msg) => {
             if let Err(e) = if msg.is_text() {
-                client.send_text(&buffer).await
+                // Text messages have already been validated as UTF-8.
+                client.send_text_bytes(&buffer).await
             } else {
                 client.send_binary(&buffer).await
             } {
-                (Err(e.into()), buffer)
+                (Err(e), buffer)
             } else {
                 (Ok(()), buffer)
             }
@@ -6,7 +6,7 @@ use monoio_ws::Config;
 #[monoio::main]
 async fn main() -> anyhow::Result<()> {
     let uri = Uri::from_static("wss://echo.websocket.org");
-    let mut ws = monoio_ws::Client::connect_tls(&uri, &Config::default()).await?;
+    let mut ws = monoio_ws::Client::connect(&uri, &Config::default()).await?;
 
     println!("Receiving welcome text.");
     let frame = ws.read_frame().await?;
@@ -24,7 +24,7 @@ async fn main() -> anyhow::Result<()> {
     println!("Received pong: {frame:?}");
 
     println!("Sending text.");
-    ws.send_text(b"hello").await?;
+    ws.send_text("hello").await?;
     println!("Sent text.");
 
     println!("Receiving text.");
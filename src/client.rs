@@ -1,22 +1,92 @@
-use std::{io, mem, result, str, sync::LazyLock};
+use std::{
+    borrow::Cow,
+    fmt, io, mem,
+    pin::Pin,
+    rc::Rc,
+    result, str,
+    time::{Duration, Instant},
+};
 
 use monoio::io::{
     AsyncReadRent, AsyncWriteRent, AsyncWriteRentExt, OwnedReadHalf, OwnedWriteHalf, Splitable,
 };
 use rand::{Rng, SeedableRng, rngs::SmallRng};
 
-use crate::{CloseCode, Frame, Message, Opcode, io::AsyncReadRentExt as _};
-
-pub static PROTOCOL_ERROR: LazyLock<Vec<u8>> = LazyLock::new(|| {
-    u16::from(CloseCode::ProtocolError)
-        .to_be_bytes()
-        .into_iter()
-        .collect()
-});
+use crate::{
+    Chunk, CloseCode, CloseFrame, CloseFrameError, ClosePolicy, CookieJar, Event, Frame, Message,
+    MessageKind, MessageReader, MessageRef, MessageWriter, Opcode, PongPolicy, ProtocolViolation,
+    RttStats, io::AsyncReadRentExt as _, rtt::RttTracker, utf8::Utf8Validator,
+};
 
 pub struct Config {
     pub read_buffer_capacity: usize,
     pub write_buffer_capacity: usize,
+    /// Maximum number of HTTP redirects to follow during the handshake. Set to
+    /// 0 to treat redirects as invalid handshake responses.
+    pub max_redirects: usize,
+    /// Cookie store shared across connections. Captures `Set-Cookie` headers
+    /// from handshake responses and sends matching cookies on later handshakes.
+    pub cookie_jar: Option<Rc<CookieJar>>,
+    /// How long [`Client::close`] waits for the peer's close frame before
+    /// shutting down the stream regardless, or indefinitely if `None`.
+    /// Requires the runtime to be built with timers enabled, so has to be set
+    /// to `None` on runtimes without them.
+    pub close_timeout: Option<Duration>,
+    /// Maximum payload length of a single received frame. Larger frames fail
+    /// the connection with [`CloseCode::MessageTooBig`] before their payload
+    /// is read. Frames received by a [`MessageReader`] aren't limited, as they
+    /// are handed out as they arrive rather than buffered in full.
+    pub max_frame_size: Option<usize>,
+    /// Maximum length of a message reassembled from fragments by
+    /// [`Client::next_msg`]. Larger messages fail the connection with
+    /// [`CloseCode::MessageTooBig`]. Messages received by a [`MessageReader`]
+    /// aren't limited, as they are never reassembled.
+    pub max_message_size: Option<usize>,
+    /// Maximum number of pings accepted from the peer per second. Exceeding it
+    /// fails the connection with [`CloseCode::PolicyViolation`].
+    pub max_pings_per_second: Option<u32>,
+    /// Replies only to the latest of several pings received in a row by
+    /// deferring the pong until no more buffered frames are left to parse, or
+    /// until a frame is sent.
+    pub coalesce_pongs: bool,
+    /// Maximum number of frames a received message may be fragmented into.
+    /// Exceeding it fails the connection with [`CloseCode::PolicyViolation`].
+    pub max_fragments: Option<usize>,
+    /// Minimum payload length of all but the final fragment of a received
+    /// message. Smaller fragments fail the connection with
+    /// [`CloseCode::PolicyViolation`].
+    pub min_fragment_size: Option<usize>,
+    /// How received pings are replied to.
+    pub pong_policy: PongPolicy,
+    /// How a close frame initiated by the peer is replied to.
+    pub close_policy: ClosePolicy,
+    /// Sends a ping once nothing has been received for this long. The ping is
+    /// only sent, and its pong only awaited, while the client is reading, so
+    /// it has to be read from regularly for a dead peer to be detected.
+    /// Requires the runtime to be built with timers enabled.
+    pub keepalive_interval: Option<Duration>,
+    /// How long to wait for the pong to a keepalive ping before failing the
+    /// connection with [`Error::KeepaliveTimeout`]. Any data received in the
+    /// meantime shows the peer to be alive, restarting the wait. No further
+    /// keepalive pings are sent until the pong echoing the ping's payload has
+    /// been received.
+    pub keepalive_timeout: Duration,
+    /// How long a read waits for data from the peer before failing with
+    /// [`Error::ReadTimeout`]. The client remains usable, completing a
+    /// partially received frame with the next call, though see the cancel
+    /// safety notes of [`Client`]. Requires the runtime to be built with
+    /// timers enabled.
+    pub read_timeout: Option<Duration>,
+    /// How long writing a frame may take before failing the connection with
+    /// [`Error::WriteTimeout`], as the frame may have been partially written.
+    /// Requires the runtime to be built with timers enabled.
+    pub write_timeout: Option<Duration>,
+    /// Maximum payload length of the frames sent by [`Client::send_text`],
+    /// [`Client::send_text_bytes`] and [`Client::send_binary`]. Larger messages
+    /// are split into fragments of this length. As a message left unfinished
+    /// can't be completed, failing to send any but the first fragment closes
+    /// the connection.
+    pub max_send_frame_size: Option<usize>,
 }
 
 impl Default for Config {
@@ -24,6 +94,22 @@ impl Default for Config {
         Self {
             read_buffer_capacity: 128 * 1024,
             write_buffer_capacity: 128 * 1024,
+            max_redirects: 0,
+            cookie_jar: None,
+            close_timeout: Some(Duration::from_secs(5)),
+            max_frame_size: Some(16 << 20),
+            max_message_size: Some(64 << 20),
+            max_pings_per_second: None,
+            coalesce_pongs: false,
+            max_fragments: None,
+            min_fragment_size: None,
+            pong_policy: PongPolicy::Automatic,
+            close_policy: ClosePolicy::Automatic,
+            keepalive_interval: None,
+            keepalive_timeout: Duration::from_secs(10),
+            read_timeout: None,
+            write_timeout: None,
+            max_send_frame_size: None,
         }
     }
 }
@@ -33,23 +119,87 @@ pub enum Error {
     #[error("IO: {0}")]
     Io(#[from] io::Error),
     #[error("Protocol violation: {0}")]
-    ProtocolViolation(&'static str),
-    #[error("The connection has been closed: {code:?} {reason:?}.")]
-    Closed {
-        code: Option<CloseCode>,
-        reason: Option<String>,
-    },
+    ProtocolViolation(#[from] ProtocolViolation),
+    #[error("The connection has been closed: {0:?}.")]
+    Closed(Option<CloseFrame>),
+    #[error("Invalid close frame: {0}")]
+    InvalidCloseFrame(#[from] CloseFrameError),
+    #[error("The connection was closed abnormally without a close frame.")]
+    AbnormalClosure,
+    #[error("The connection is closing or has been closed.")]
+    AlreadyClosed,
+    #[error("No pong was received in response to a keepalive ping.")]
+    KeepaliveTimeout,
+    #[error("Timed out waiting for data from the peer.")]
+    ReadTimeout,
+    #[error("Timed out writing a frame.")]
+    WriteTimeout,
+    #[error("Data frame out of sequence with the fragmented message being sent.")]
+    OutOfSequenceFrame,
+    #[error("A frame was left partially written by a dropped send.")]
+    IncompleteWrite,
+    #[error("A partially received message has to be finished the way it was started.")]
+    MessageInProgress,
+}
+
+impl Error {
+    /// Returns the close code describing how the connection was closed, if
+    /// this error signals it being closed or failed.
+    ///
+    /// A close frame without a code yields [`CloseCode::NoStatusReceived`] and
+    /// a connection closed without a close frame [`CloseCode::Abnormal`]. A
+    /// protocol violation yields the code which was sent to the peer.
+    #[must_use]
+    pub fn close_code(&self) -> Option<CloseCode> {
+        match self {
+            Self::ProtocolViolation(violation) => Some(violation.close_code()),
+            Self::Closed(Some(frame)) => Some(frame.code),
+            Self::Closed(None) => Some(CloseCode::NoStatusReceived),
+            Self::AbnormalClosure => Some(CloseCode::Abnormal),
+            _ => None,
+        }
+    }
 }
 
 pub type BufResult<T> = (result::Result<T, Error>, Vec<u8>);
 pub type Result<T> = result::Result<T, Error>;
 
+/// State of the connection with respect to the closing handshake:
+/// <https://datatracker.ietf.org/doc/html/rfc6455#section-7>
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// Messages may be sent and received.
+    Open,
+    /// A close frame has been either sent or received, but not both.
+    Closing,
+    /// Close frames have been both sent and received, or the underlying stream
+    /// has been shut down.
+    Closed,
+}
+
+/// A WebSocket client over the stream `S`.
+///
+/// # Cancel safety
+///
+/// Received data is buffered by the client, so that dropping a future which
+/// receives, such as a branch losing a [`monoio::select!`], keeps a partially
+/// received frame or message to be completed by the next call. A read from
+/// the stream left pending, whether by a dropped future or a timeout, is kept
+/// by the client and resumed by the next call, so none of the data it
+/// receives is lost. Replies to received frames and keepalive pings, which
+/// receiving may write, are kept the same way until written in full.
+///
+/// Sending is not cancel safe. A send dropped while writing may leave a frame
+/// partially written, after which the connection is closed and later sends
+/// fail with [`Error::IncompleteWrite`].
 pub struct Client<S>
 where
     S: AsyncWriteRent,
 {
     read_half: ReadHalf<S>,
     write_half: WriteHalf<S>,
+    partial: PartialMessage,
+    close_timeout: Option<Duration>,
 }
 
 impl<S> Client<S>
@@ -57,277 +207,906 @@ where
     S: AsyncWriteRent + Splitable<OwnedRead = OwnedReadHalf<S>, OwnedWrite = OwnedWriteHalf<S>>,
 {
     pub fn new(stream: S, config: &Config) -> Self {
+        Self::from_upgraded(stream, &[], config)
+    }
+
+    /// Creates a client from a stream which has already completed the WebSocket
+    /// upgrade elsewhere. Any bytes read past the end of the handshake response
+    /// must be passed as `leftover` so that they are parsed as frames.
+    pub fn from_upgraded(stream: S, leftover: &[u8], config: &Config) -> Self {
         let (read_half, write_half) = stream.into_split();
+        let mut buffer = Vec::with_capacity(config.read_buffer_capacity.max(leftover.len()));
+        buffer.extend_from_slice(leftover);
         Self {
             read_half: ReadHalf {
-                inner: read_half,
-                buffer: Vec::with_capacity(config.read_buffer_capacity),
+                inner: Some(read_half),
+                pending_read: None,
+                buffer,
                 consumed: 0,
+                streamed: None,
+                max_frame_size: config.max_frame_size,
+                max_pings_per_second: config.max_pings_per_second,
+                ping_window_start: Instant::now(),
+                ping_window_count: 0,
+                last_received: Instant::now(),
+                keepalive_interval: config.keepalive_interval,
+                keepalive_timeout: config.keepalive_timeout,
+                read_timeout: config.read_timeout,
             },
             write_half: WriteHalf {
-                inner: write_half,
+                inner: Some(write_half),
+                pending_write: None,
+                writing: false,
                 rng: SmallRng::from_os_rng(),
                 buffer: Vec::with_capacity(config.write_buffer_capacity),
+                replies: Vec::new(),
+                close_sent: false,
+                close_received: false,
+                shutdown: false,
+                sending_message: None,
+                coalesce_pongs: config.coalesce_pongs,
+                pending_pong: None,
+                pong_policy: config.pong_policy.clone(),
+                close_policy: config.close_policy.clone(),
+                rtt: RttTracker::new(),
+                keepalive_ping: None,
+                write_timeout: config.write_timeout,
+                max_send_frame_size: config.max_send_frame_size,
+            },
+            partial: PartialMessage {
+                kind: None,
+                streaming: false,
+                fragments: 0,
+                len: 0,
+                data: Vec::new(),
+                utf8: Utf8Validator::default(),
+                max_message_size: config.max_message_size,
+                max_fragments: config.max_fragments,
+                min_fragment_size: config.min_fragment_size,
             },
+            close_timeout: config.close_timeout,
         }
     }
+
+    /// Takes the client apart, returning the underlying stream together with
+    /// any bytes which have been read from it but not yet parsed as frames.
+    ///
+    /// A read or write left pending by a dropped future or a timeout is
+    /// completed first, so that none of the data it receives is lost, and
+    /// queued replies to received frames are sent. A partially received
+    /// message would be lost though, so until it has been finished, this fails
+    /// with [`Error::MessageInProgress`], handing back the client.
+    pub async fn into_parts(mut self) -> result::Result<(S, Vec<u8>), IntoPartsError<S>>
+    where
+        S: 'static,
+    {
+        if self.partial.kind.is_some() {
+            return Err(IntoPartsError {
+                client: self,
+                error: Error::MessageInProgress,
+            });
+        }
+        if let Err(e) = self.read_half.finish_read().await {
+            return Err(IntoPartsError {
+                client: self,
+                error: e.into(),
+            });
+        }
+        let res = match self.write_half.queue_pending_pong() {
+            Ok(()) => self.write_half.flush().await,
+            Err(e) => Err(e),
+        };
+        if let Err(error) = res {
+            return Err(IntoPartsError {
+                client: self,
+                error,
+            });
+        }
+
+        let Self {
+            read_half,
+            write_half,
+            ..
+        } = self;
+        let leftover = read_half.buffer[read_half.consumed..].to_vec();
+        let Some(read_inner) = read_half.inner else {
+            unreachable!("The read half is returned by a completed read.");
+        };
+        let stream = match write_half.inner {
+            Some(write_inner) => match read_inner.reunite(write_inner) {
+                Ok(stream) => stream,
+                Err(_) => unreachable!("Halves always originate from the same stream."),
+            },
+            // The write half has been dropped along with a write abandoned on
+            // shutdown.
+            None => match Rc::try_unwrap(read_inner.0) {
+                Ok(stream) => stream.into_inner(),
+                Err(_) => unreachable!("Only the halves share the stream."),
+            },
+        };
+        Ok((stream, leftover))
+    }
+}
+
+/// Error returned by [`Client::into_parts`], handing back the client.
+#[derive(thiserror::Error)]
+#[error("{error}")]
+pub struct IntoPartsError<S>
+where
+    S: AsyncWriteRent,
+{
+    client: Client<S>,
+    error: Error,
+}
+
+impl<S> IntoPartsError<S>
+where
+    S: AsyncWriteRent,
+{
+    #[must_use]
+    pub fn error(&self) -> &Error {
+        &self.error
+    }
+
+    #[must_use]
+    pub fn into_client(self) -> Client<S> {
+        self.client
+    }
+}
+
+impl<S> fmt::Debug for IntoPartsError<S>
+where
+    S: AsyncWriteRent,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IntoPartsError")
+            .field("error", &self.error)
+            .finish_non_exhaustive()
+    }
 }
 
 impl<S> Client<S>
 where
-    S: AsyncReadRent + AsyncWriteRent,
+    S: AsyncReadRent + AsyncWriteRent + 'static,
 {
-    pub async fn next_msg(&mut self, buffer: Vec<u8>) -> BufResult<Message> {
-        self.read_half.next_msg(&mut self.write_half, buffer).await
+    /// Receives the next complete message into `buffer`, reassembling
+    /// fragments and replying to control frames received in between.
+    ///
+    /// # Cancel safety
+    ///
+    /// This method is cancel safe. If it is dropped, the fragments received
+    /// so far are kept and the message is completed by the next call. Only
+    /// the buffer passed in is lost.
+    pub async fn next_msg(&mut self, buffer: Vec<u8>) -> BufResult<MessageKind> {
+        self.next_msg_until(buffer, None).await
+    }
+
+    /// Like [`Client::next_msg`], but fails with [`Error::ReadTimeout`] if no
+    /// complete message has been received within `timeout`. The client remains
+    /// usable, and a partially received message is completed by the next call.
+    ///
+    /// # Cancel safety
+    ///
+    /// This method is cancel safe, see [`Client::next_msg`].
+    pub async fn next_msg_timeout(
+        &mut self,
+        timeout: Duration,
+        buffer: Vec<u8>,
+    ) -> BufResult<MessageKind> {
+        let deadline = Instant::now() + timeout;
+        self.next_msg_until(buffer, Some(deadline)).await
+    }
+
+    async fn next_msg_until(
+        &mut self,
+        mut buffer: Vec<u8>,
+        deadline: Option<Instant>,
+    ) -> BufResult<MessageKind> {
+        loop {
+            let (res, buf) = self
+                .read_half
+                .next_event(&mut self.write_half, &mut self.partial, buffer, deadline)
+                .await;
+            buffer = buf;
+            let msg = match res {
+                Ok(Event::Text) => MessageKind::Text,
+                Ok(Event::Binary) => MessageKind::Binary,
+                Ok(_) => continue,
+                Err(e) => return (Err(e), buffer),
+            };
+            return (Ok(msg), buffer);
+        }
+    }
+
+    /// Receives the next message, ping, pong or close frame into `buffer`.
+    /// Unlike [`Client::next_msg`], a close frame from the peer is reported as
+    /// [`Event::Close`] rather than an error.
+    ///
+    /// # Cancel safety
+    ///
+    /// This method is cancel safe, see [`Client::next_msg`].
+    pub async fn next_event(&mut self, buffer: Vec<u8>) -> BufResult<Event> {
+        let (res, buffer) = self
+            .read_half
+            .next_event(&mut self.write_half, &mut self.partial, buffer, None)
+            .await;
+        let res = match res {
+            Err(Error::Closed(frame)) => Ok(Event::Close(frame)),
+            res => res,
+        };
+        (res, buffer)
     }
 
-    pub async fn read_frame(&mut self) -> Result<Frame> {
-        self.read_half.read_frame(&mut self.write_half).await
+    /// Receives the next frame, replying to pings and close frames as
+    /// configured.
+    ///
+    /// # Cancel safety
+    ///
+    /// This method is cancel safe. If it is dropped before a frame has been
+    /// received in full, the bytes read so far are kept and the frame is
+    /// returned by the next call.
+    pub async fn read_frame(&mut self) -> Result<Frame<'_>> {
+        self.read_half.read_frame(&mut self.write_half, None).await
     }
 
-    pub async fn send_ping(&mut self, data: &[u8]) -> io::Result<()> {
+    /// Like [`Client::read_frame`], but fails with [`Error::ReadTimeout`] once
+    /// `deadline` passes. The client remains usable, and a partially received
+    /// frame is returned by the next call.
+    ///
+    /// # Cancel safety
+    ///
+    /// This method is cancel safe, see [`Client::read_frame`].
+    pub async fn read_frame_until(&mut self, deadline: Instant) -> Result<Frame<'_>> {
+        self.read_half
+            .read_frame(&mut self.write_half, Some(deadline))
+            .await
+    }
+
+    #[must_use]
+    pub fn state(&self) -> ConnectionState {
+        self.write_half.state()
+    }
+
+    pub async fn send_ping(&mut self, data: &[u8]) -> Result<()> {
         self.write_half.send_ping(data).await
     }
 
-    pub async fn send_pong(&mut self, data: &[u8]) -> io::Result<()> {
+    pub async fn send_pong(&mut self, data: &[u8]) -> Result<()> {
         self.write_half.send_pong(data).await
     }
 
-    pub async fn send_binary(&mut self, data: &[u8]) -> io::Result<()> {
-        self.write_half.send_binary(data).await
+    /// Sends a ping carrying a sequence number and timestamp. The round-trip
+    /// time is measured once the matching pong is read and reported by
+    /// [`Client::rtt`].
+    pub async fn send_rtt_ping(&mut self) -> Result<()> {
+        let payload = self.write_half.rtt.ping_payload(Instant::now());
+        self.write_half.send_ping(&payload).await
+    }
+
+    #[must_use]
+    pub fn rtt(&self) -> RttStats {
+        self.write_half.rtt.stats()
+    }
+
+    pub async fn send_binary(&mut self, data: impl AsRef<[u8]>) -> Result<()> {
+        self.write_half.send_binary(data.as_ref()).await
+    }
+
+    pub async fn send_text(&mut self, text: &str) -> Result<()> {
+        self.write_half.send_text(text.as_bytes()).await
+    }
+
+    /// Like [`Client::send_text`], but takes text already known to be valid
+    /// UTF-8 as bytes, such as a received text message, without checking it
+    /// again. Sending invalid UTF-8 makes the peer fail the connection.
+    pub async fn send_text_bytes(&mut self, text: &[u8]) -> Result<()> {
+        self.write_half.send_text(text).await
+    }
+
+    /// Receives the next message with an owned payload. Allocates for every
+    /// message, so prefer [`Client::next_msg`] on hot paths.
+    pub async fn recv(&mut self) -> Result<Message> {
+        let (res, data) = self.next_msg(Vec::new()).await;
+        Ok(match res? {
+            // SAFETY: Text messages have been validated as UTF-8 as they were
+            // received, failing the connection otherwise.
+            MessageKind::Text => Message::Text(unsafe { String::from_utf8_unchecked(data) }),
+            MessageKind::Binary => Message::Binary(data),
+        })
+    }
+
+    /// Receives the next message, borrowing its payload from the client. A
+    /// message which arrived in a single frame is borrowed straight from the
+    /// read buffer, while a fragmented one is reassembled in an internal
+    /// buffer first.
+    ///
+    /// # Cancel safety
+    ///
+    /// This method is cancel safe, see [`Client::next_msg`].
+    pub async fn recv_ref(&mut self) -> Result<MessageRef<'_>> {
+        let (kind, len) = self
+            .read_half
+            .next_msg_ref(&mut self.write_half, &mut self.partial, None)
+            .await?;
+        let data = match len {
+            Some(len) => {
+                let end = self.read_half.consumed;
+                &self.read_half.buffer[end - len..end]
+            }
+            None => &self.partial.data,
+        };
+        Ok(match kind {
+            // SAFETY: Text messages have been validated as UTF-8 as they were
+            // received, failing the connection otherwise.
+            MessageKind::Text => MessageRef::Text(unsafe { str::from_utf8_unchecked(data) }),
+            MessageKind::Binary => MessageRef::Binary(data),
+        })
+    }
+
+    pub async fn send(&mut self, message: Message) -> Result<()> {
+        match message {
+            Message::Text(text) => self.send_text(&text).await,
+            Message::Binary(data) => self.send_binary(data).await,
+        }
+    }
+
+    /// Starts receiving the next message in chunks as they arrive, rather than
+    /// reassembling it first like [`Client::next_msg`].
+    pub fn message_reader(&mut self) -> MessageReader<'_, S> {
+        MessageReader::new(self)
+    }
+
+    pub(crate) async fn next_chunk(&mut self) -> Result<Chunk<'_>> {
+        self.read_half
+            .next_chunk(&mut self.write_half, &mut self.partial, None)
+            .await
     }
 
-    pub async fn send_text(&mut self, data: &[u8]) -> io::Result<()> {
-        self.write_half.send_text(data).await
+    /// Starts sending a message of `kind` in fragments.
+    pub fn message_writer(&mut self, kind: MessageKind) -> MessageWriter<'_, S> {
+        MessageWriter::new(self, kind)
     }
 
-    pub async fn send_close(&mut self, data: &[u8]) -> io::Result<()> {
-        self.write_half.send_text(data).await
+    /// Kind of the fragmented message which has been started but not finished.
+    pub(crate) fn sending_message(&self) -> Option<MessageKind> {
+        self.write_half.sending_message
     }
 
-    pub async fn write_frame(&mut self, frame: Frame<'_>) -> io::Result<()> {
+    /// Sends a close frame without waiting for the peer's response. Prefer
+    /// [`Client::close`] which completes the closing handshake.
+    ///
+    /// When replying to a close frame left unanswered by
+    /// [`ClosePolicy::Manual`], the stream is shut down afterwards.
+    pub async fn send_close(&mut self, frame: Option<&CloseFrame>) -> Result<()> {
+        let mut payload = Vec::with_capacity(2 + CloseFrame::MAX_REASON_LEN);
+        if let Some(frame) = frame {
+            frame.encode(&mut payload)?;
+        }
+        self.write_half.send_close(&payload).await?;
+        if self.write_half.close_received {
+            self.write_half.shutdown().await?;
+        }
+        Ok(())
+    }
+
+    /// Performs the closing handshake: sends a close frame with `code` and
+    /// `reason`, waits up to [`Config::close_timeout`] for the peer's close
+    /// frame and finally shuts down the underlying stream. Any data frames
+    /// received in the meantime are discarded. The reason is truncated to
+    /// [`CloseFrame::MAX_REASON_LEN`] bytes.
+    pub async fn close(&mut self, code: CloseCode, reason: &str) -> Result<()> {
+        self.send_close(Some(&CloseFrame::new(code, reason)))
+            .await?;
+
+        let res = if self.write_half.close_received {
+            Ok(())
+        } else {
+            let deadline = self
+                .close_timeout
+                .map(|close_timeout| Instant::now() + close_timeout);
+            match self.wait_for_close(deadline).await {
+                // The stream is shut down regardless of the peer not responding.
+                Err(Error::ReadTimeout) => Ok(()),
+                res => res,
+            }
+        };
+
+        let shutdown = self.write_half.shutdown().await;
+        res.and(shutdown)
+    }
+
+    async fn wait_for_close(&mut self, deadline: Option<Instant>) -> Result<()> {
+        loop {
+            let frame = self
+                .read_half
+                .read_frame(&mut self.write_half, deadline)
+                .await?;
+            if matches!(frame.opcode, Opcode::Close) {
+                return Ok(());
+            }
+        }
+    }
+
+    pub async fn write_frame(&mut self, frame: Frame<'_>) -> Result<()> {
         self.write_half.write_frame(frame).await
     }
 }
 
+/// State of a message being reassembled from fragments, kept across calls so
+/// that receiving it can be resumed after a timeout.
+struct PartialMessage {
+    kind: Option<MessageKind>,
+    /// Whether the message is handed out in chunks rather than reassembled.
+    streaming: bool,
+    fragments: usize,
+    len: usize,
+    data: Vec<u8>,
+    utf8: Utf8Validator,
+    max_message_size: Option<usize>,
+    max_fragments: Option<usize>,
+    min_fragment_size: Option<usize>,
+}
+
+impl PartialMessage {
+    fn reset(&mut self) {
+        self.end();
+        self.data.clear();
+    }
+
+    /// Ends the message, keeping its payload to be borrowed until the next
+    /// message starts.
+    fn end(&mut self) {
+        self.kind = None;
+        self.streaming = false;
+        self.fragments = 0;
+        self.len = 0;
+        self.utf8.reset();
+    }
+
+    /// Accounts for a received data frame with a payload of `len` bytes,
+    /// returning the kind of message it belongs to.
+    fn begin_frame(
+        &mut self,
+        opcode: Opcode,
+        fin: bool,
+        len: usize,
+    ) -> result::Result<MessageKind, ProtocolViolation> {
+        let kind = match (opcode, self.kind) {
+            (Opcode::Text, None) => MessageKind::Text,
+            (Opcode::Binary, None) => MessageKind::Binary,
+            (Opcode::Continuation, Some(kind)) => kind,
+            (Opcode::Continuation, None) => return Err(ProtocolViolation::UnexpectedContinuation),
+            (Opcode::Text | Opcode::Binary, Some(_)) => {
+                return Err(ProtocolViolation::ExpectedContinuation);
+            }
+            _ => unreachable!(),
+        };
+
+        if self.kind.is_none() {
+            // Discard the payload of a previous message received by
+            // `Client::recv_ref`.
+            self.data.clear();
+        }
+        self.fragments += 1;
+        if self.max_fragments.is_some_and(|max| self.fragments > max) {
+            return Err(ProtocolViolation::TooManyFragments);
+        }
+        if !fin && self.min_fragment_size.is_some_and(|min| len < min) {
+            return Err(ProtocolViolation::FragmentTooSmall);
+        }
+        if !self.streaming
+            && self
+                .max_message_size
+                .is_some_and(|max| self.len + len > max)
+        {
+            return Err(ProtocolViolation::MessageTooBig);
+        }
+        self.kind = Some(kind);
+        self.len += len;
+        Ok(kind)
+    }
+}
+
+/// Data frame whose payload is being handed out in chunks as it arrives.
+#[derive(Clone, Copy)]
+struct StreamedFrame {
+    kind: MessageKind,
+    fin: bool,
+    remaining: usize,
+}
+
+/// A read from the stream into the read buffer. It is parked in the read half
+/// while pending, owning the half and the buffer until it completes, so that
+/// dropping the future which started it loses none of the data it receives.
+type PendingRead<S> =
+    Pin<Box<dyn Future<Output = (OwnedReadHalf<S>, (io::Result<usize>, Vec<u8>))>>>;
+
 struct ReadHalf<S> {
-    inner: OwnedReadHalf<S>,
+    /// Taken by the pending read, if any.
+    inner: Option<OwnedReadHalf<S>>,
+    pending_read: Option<PendingRead<S>>,
+    /// Taken by the pending read, if any.
     buffer: Vec<u8>,
     consumed: usize,
+    streamed: Option<StreamedFrame>,
+    max_frame_size: Option<usize>,
+    max_pings_per_second: Option<u32>,
+    ping_window_start: Instant,
+    ping_window_count: u32,
+    /// When data was last received, which the keepalive interval starts at.
+    last_received: Instant,
+    keepalive_interval: Option<Duration>,
+    keepalive_timeout: Duration,
+    read_timeout: Option<Duration>,
+}
+
+impl<S> ReadHalf<S> {
+    /// Completes the pending read, if any, returning its result.
+    async fn finish_read(&mut self) -> io::Result<usize> {
+        let Some(read) = self.pending_read.as_mut() else {
+            return Ok(0);
+        };
+        let (inner, (res, buffer)) = read.await;
+        self.end_read(inner, buffer);
+        res
+    }
+
+    /// Takes back the read half and the buffer from the completed read.
+    fn end_read(&mut self, inner: OwnedReadHalf<S>, buffer: Vec<u8>) {
+        self.pending_read = None;
+        self.inner = Some(inner);
+        self.buffer = buffer;
+    }
 }
 
 impl<S> ReadHalf<S>
 where
-    S: AsyncReadRent + AsyncWriteRent,
+    S: AsyncReadRent + AsyncWriteRent + 'static,
 {
     const CHUNK_SIZE: usize = 4096;
 
-    pub async fn next_msg<'a>(
-        &'a mut self,
-        write: &'a mut WriteHalf<S>,
+    /// Receives the next message, ping or pong into `buffer`. A close frame is
+    /// reported as [`Error::Closed`].
+    pub async fn next_event(
+        &mut self,
+        write: &mut WriteHalf<S>,
+        partial: &mut PartialMessage,
         mut buffer: Vec<u8>,
-    ) -> BufResult<Message> {
+        deadline: Option<Instant>,
+    ) -> BufResult<Event> {
         buffer.clear();
-        let mut message = None;
+        if partial.streaming {
+            return (Err(Error::MessageInProgress), buffer);
+        }
+        let res = self
+            .next_event_inner(write, partial, &mut buffer, deadline)
+            .await;
+        match res {
+            Ok(Event::Text | Event::Binary) => {
+                // Hand out the message and reuse the caller's buffer for the
+                // next one.
+                mem::swap(&mut buffer, &mut partial.data);
+                partial.reset();
+            }
+            // Control frames may arrive in between fragments of a message.
+            Ok(_) => {}
+            // Resume the partially received message with the next call.
+            Err(Error::ReadTimeout) => {}
+            Err(_) => partial.reset(),
+        }
+        (res, buffer)
+    }
 
+    async fn next_event_inner(
+        &mut self,
+        write: &mut WriteHalf<S>,
+        partial: &mut PartialMessage,
+        buffer: &mut Vec<u8>,
+        deadline: Option<Instant>,
+    ) -> Result<Event> {
         loop {
-            let is_empty = buffer.is_empty();
-            let frame = match self.read_frame(write).await {
-                Ok(frame) => frame,
-                Err(e) => return (Err(e), buffer),
-            };
+            let frame = self.read_frame(write, deadline).await?;
 
             match frame.opcode {
-                Opcode::Continuation => match message {
-                    Some(Message::Text) if frame.fin => {
-                        buffer.extend_from_slice(frame.data);
-                        if Frame::validate_utf8(&buffer).is_some() {
-                            return (Ok(Message::Text), buffer);
-                        } else {
-                            if let Err(e) = write.send_close(&PROTOCOL_ERROR).await {
-                                return (Err(e.into()), buffer);
-                            };
-                            return (
-                                Err(Error::ProtocolViolation(
-                                    "Received text frame with invalid utf-8.",
-                                )),
-                                buffer,
-                            );
-                        }
-                    }
-                    Some(Message::Binary) if frame.fin => {
-                        buffer.extend_from_slice(frame.data);
-                        return (Ok(Message::Binary), buffer);
-                    }
-                    Some(Message::Text | Message::Binary) => {
-                        buffer.extend_from_slice(frame.data);
-                    }
-                    None => {
-                        if let Err(e) = write.send_close(&PROTOCOL_ERROR).await {
-                            return (Err(e.into()), buffer);
-                        };
-                        return (
-                            Err(Error::ProtocolViolation(
-                                "Received continuation frame without preceding text or binary frame.",
-                            )),
-                            buffer,
-                        );
-                    }
-                },
-                Opcode::Text if frame.fin => {
-                    if !is_empty {
-                        if let Err(e) = write.send_close(&PROTOCOL_ERROR).await {
-                            return (Err(e.into()), buffer);
-                        };
-                        return (
-                            Err(Error::ProtocolViolation(
-                                "Received a continuation frame without continuation opcode.",
-                            )),
-                            buffer,
-                        );
-                    }
-                    if Frame::validate_utf8(frame.data).is_some() {
-                        buffer.extend_from_slice(frame.data);
-                        return (Ok(Message::Text), buffer);
-                    } else {
-                        if let Err(e) = write.send_close(&PROTOCOL_ERROR).await {
-                            return (Err(e.into()), buffer);
-                        };
-                        return (
-                            Err(Error::ProtocolViolation(
-                                "Received text frame with invalid utf-8.",
-                            )),
-                            buffer,
-                        );
-                    }
+                Opcode::Close => {
+                    // Already validated when reading the frame.
+                    let close_frame = CloseFrame::decode(frame.data).ok().flatten();
+                    return Err(Error::Closed(close_frame));
                 }
-                Opcode::Text => {
+                Opcode::Ping => {
                     buffer.extend_from_slice(frame.data);
-                    message = Some(Message::Text);
+                    return Ok(Event::Ping);
                 }
-                Opcode::Binary if frame.fin => {
-                    if !is_empty {
-                        if let Err(e) = write.send_close(&PROTOCOL_ERROR).await {
-                            return (Err(e.into()), buffer);
-                        };
-                        return (
-                            Err(Error::ProtocolViolation(
-                                "Received a continuation frame without continuation opcode.",
-                            )),
-                            buffer,
-                        );
-                    }
+                Opcode::Pong => {
                     buffer.extend_from_slice(frame.data);
-                    return (Ok(Message::Binary), buffer);
+                    return Ok(Event::Pong);
                 }
-                Opcode::Binary => {
-                    buffer.extend_from_slice(frame.data);
-                    message = Some(Message::Binary);
+                _ => {}
+            }
+
+            let kind = match partial.begin_frame(frame.opcode, frame.fin, frame.data.len()) {
+                Ok(kind) => kind,
+                Err(violation) => return Err(write.violation(violation).await),
+            };
+            // Fail as soon as a fragment is invalid rather than at the end.
+            if kind.is_text() && !partial.utf8.feed(frame.data, frame.fin) {
+                return Err(write.violation(ProtocolViolation::InvalidUtf8).await);
+            }
+            partial.data.extend_from_slice(frame.data);
+            if frame.fin {
+                return Ok(match kind {
+                    MessageKind::Text => Event::Text,
+                    MessageKind::Binary => Event::Binary,
+                });
+            }
+        }
+    }
+
+    /// Receives the next message without copying its payload if it arrived in
+    /// a single frame. Returns its kind and, in that case, the payload length,
+    /// such that the payload ends where the buffer has been consumed up to.
+    /// Otherwise the payload is reassembled in `partial`.
+    pub async fn next_msg_ref(
+        &mut self,
+        write: &mut WriteHalf<S>,
+        partial: &mut PartialMessage,
+        deadline: Option<Instant>,
+    ) -> Result<(MessageKind, Option<usize>)> {
+        if partial.streaming {
+            return Err(Error::MessageInProgress);
+        }
+        let res = self.next_msg_ref_inner(write, partial, deadline).await;
+        match res {
+            Ok(_) => partial.end(),
+            // Resume the partially received message with the next call.
+            Err(Error::ReadTimeout) => {}
+            Err(_) => partial.reset(),
+        }
+        res
+    }
+
+    async fn next_msg_ref_inner(
+        &mut self,
+        write: &mut WriteHalf<S>,
+        partial: &mut PartialMessage,
+        deadline: Option<Instant>,
+    ) -> Result<(MessageKind, Option<usize>)> {
+        loop {
+            let frame = self.read_frame(write, deadline).await?;
+
+            match frame.opcode {
+                Opcode::Close => {
+                    // Already validated when reading the frame.
+                    let close_frame = CloseFrame::decode(frame.data).ok().flatten();
+                    return Err(Error::Closed(close_frame));
+                }
+                Opcode::Ping | Opcode::Pong => continue,
+                _ => {}
+            }
+
+            let kind = match partial.begin_frame(frame.opcode, frame.fin, frame.data.len()) {
+                Ok(kind) => kind,
+                Err(violation) => return Err(write.violation(violation).await),
+            };
+            if kind.is_text() && !partial.utf8.feed(frame.data, frame.fin) {
+                return Err(write.violation(ProtocolViolation::InvalidUtf8).await);
+            }
+            if frame.fin && partial.fragments == 1 {
+                return Ok((kind, Some(frame.data.len())));
+            }
+            partial.data.extend_from_slice(frame.data);
+            if frame.fin {
+                return Ok((kind, None));
+            }
+        }
+    }
+
+    /// Returns the next chunk of a data frame's payload as soon as any of it
+    /// has been received, replying to control frames received in between.
+    pub async fn next_chunk<'a>(
+        &'a mut self,
+        write: &mut WriteHalf<S>,
+        partial: &mut PartialMessage,
+        deadline: Option<Instant>,
+    ) -> Result<Chunk<'a>> {
+        if partial.kind.is_some() && !partial.streaming {
+            return Err(Error::MessageInProgress);
+        }
+        match self.next_chunk_inner(write, partial, deadline).await {
+            Ok((kind, fin, len)) => Ok(Chunk {
+                kind,
+                fin,
+                data: &self.buffer[self.consumed - len..self.consumed],
+            }),
+            Err(Error::ReadTimeout) => Err(Error::ReadTimeout),
+            Err(e) => {
+                partial.reset();
+                self.streamed = None;
+                Err(write.read_failed(e).await)
+            }
+        }
+    }
+
+    /// Returns the kind of the chunk, whether it is final and its length, such
+    /// that it ends where the buffer has been consumed up to.
+    async fn next_chunk_inner(
+        &mut self,
+        write: &mut WriteHalf<S>,
+        partial: &mut PartialMessage,
+        deadline: Option<Instant>,
+    ) -> Result<(MessageKind, bool, usize)> {
+        loop {
+            if let Some(streamed) = self.streamed {
+                if streamed.remaining > 0
+                    && (self.pending_read.is_some() || self.consumed == self.buffer.len())
+                {
+                    self.ensure_read(write, 1, deadline).await?;
+                }
+
+                let len = streamed.remaining.min(self.buffer.len() - self.consumed);
+                let remaining = streamed.remaining - len;
+                let fin = streamed.fin && remaining == 0;
+                self.streamed = (remaining > 0).then_some(StreamedFrame {
+                    remaining,
+                    ..streamed
+                });
+                let data = &self.buffer[self.consumed..self.consumed + len];
+                self.consumed += len;
+                if streamed.kind.is_text() && !partial.utf8.feed(data, fin) {
+                    return Err(ProtocolViolation::InvalidUtf8.into());
+                }
+                if fin {
+                    partial.reset();
+                }
+                return Ok((streamed.kind, fin, len));
+            }
+
+            if write.close_received {
+                return Err(Error::AlreadyClosed);
+            }
+            let (fin, opcode, length, header_len) = self.parse_header(write, deadline).await?;
+            match opcode {
+                Opcode::Text | Opcode::Binary | Opcode::Continuation => {
+                    partial.streaming = true;
+                    let kind = partial.begin_frame(opcode, fin, length)?;
+                    self.consumed += header_len;
+                    self.streamed = Some(StreamedFrame {
+                        kind,
+                        fin,
+                        remaining: length,
+                    });
                 }
                 Opcode::Close => {
-                    let code = if frame.data.len() >= 2 {
-                        // Ok to unwrap as code is already validated.
-                        let close_code =
-                            CloseCode::try_from(u16::from_be_bytes([frame.data[0], frame.data[1]]))
-                                .unwrap();
-                        Some(close_code)
-                    } else {
-                        None
-                    };
-                    // Everything after close code is a utf-8 reason string.
-                    let reason = if frame.data.len() > 2 {
-                        Some(unsafe { str::from_utf8_unchecked(&frame.data[2..]) })
-                    } else {
-                        None
-                    };
-                    return (
-                        Err(Error::Closed {
-                            code,
-                            reason: reason.map(ToOwned::to_owned),
-                        }),
-                        buffer,
-                    );
+                    // The header has not been consumed, so read the whole
+                    // frame to reply to it.
+                    let frame = self.read_frame(write, deadline).await?;
+                    let close_frame = CloseFrame::decode(frame.data).ok().flatten();
+                    return Err(Error::Closed(close_frame));
+                }
+                _ => {
+                    self.read_frame(write, deadline).await?;
                 }
-                Opcode::Ping | Opcode::Pong => {}
-                _ => unreachable!(),
             }
         }
     }
 
-    pub async fn read_frame<'a>(&'a mut self, write: &mut WriteHalf<S>) -> Result<Frame<'a>> {
-        match self.read_frame_inner().await {
+    pub async fn read_frame<'a>(
+        &'a mut self,
+        write: &mut WriteHalf<S>,
+        deadline: Option<Instant>,
+    ) -> Result<Frame<'a>> {
+        // Nothing may follow a close frame.
+        if write.close_received {
+            return Err(Error::AlreadyClosed);
+        }
+
+        match self.read_frame_inner(write, deadline).await {
             Ok(frame) if matches!(frame.opcode, Opcode::Ping) => {
-                // Auto-send pong frame.
-                write
-                    .write_control_frame(Frame {
-                        fin: true,
-                        opcode: Opcode::Pong,
-                        data: frame.data,
-                    })
-                    .await?;
+                write.reply_to_ping(frame.data)?;
+                write.flush().await?;
+                Ok(frame)
+            }
+            Ok(frame) if matches!(frame.opcode, Opcode::Pong) => {
+                if write
+                    .keepalive_ping
+                    .is_some_and(|(payload, _)| frame.data == payload)
+                {
+                    write.keepalive_ping = None;
+                }
+                write.rtt.on_pong(frame.data, Instant::now());
                 Ok(frame)
             }
             Ok(frame) if matches!(frame.opcode, Opcode::Close) => {
-                let _code = if frame.data.len() >= 2 {
-                    let Ok(close_code) =
-                        CloseCode::try_from(u16::from_be_bytes([frame.data[0], frame.data[1]]))
-                    else {
-                        write.send_close(&PROTOCOL_ERROR).await?;
-                        return Err(Error::ProtocolViolation("Invalid close code."));
-                    };
-                    if close_code.is_reserved() {
-                        write.send_close(&PROTOCOL_ERROR).await?;
-                        return Err(Error::ProtocolViolation("Received reserved close code."));
-                    }
-                    Some(close_code)
-                } else {
-                    None
-                };
-                // Everything after close code is a utf-8 reason string.
-                let _reason = if frame.data.len() > 2 {
-                    let Some(reason) = Frame::validate_utf8(&frame.data[2..]) else {
-                        write.send_close(&PROTOCOL_ERROR).await?;
-                        return Err(Error::ProtocolViolation(
-                            "Received close frame with invalid utf-8 reason.",
-                        ));
-                    };
-                    Some(reason)
-                } else {
-                    None
+                let close_frame = match CloseFrame::decode(frame.data) {
+                    Ok(close_frame) => close_frame,
+                    Err(e) => return Err(write.violation(e.into()).await),
                 };
-                // Auto-send close with the same code as received.
-                write
-                    .write_control_frame(Frame {
-                        fin: true,
-                        opcode: Opcode::Close,
-                        data: frame.data,
-                    })
-                    .await?;
+                write.reply_to_close(frame.data, close_frame.as_ref())?;
+                write.flush().await?;
+                if write.close_sent {
+                    // Both sides have sent a close frame, so the closing
+                    // handshake is complete.
+                    write.shutdown().await?;
+                }
                 Ok(frame)
             }
             Ok(frame) => Ok(frame),
-            Err(Error::ProtocolViolation(reason)) => {
-                write.send_close(&PROTOCOL_ERROR).await?;
-                Err(Error::ProtocolViolation(reason))
-            }
-            Err(err) => Err(err),
+            Err(e) => Err(write.read_failed(e).await),
         }
     }
 
     #[inline]
-    async fn read_frame_inner(&mut self) -> Result<Frame> {
-        const HEADER_LEN: usize = 2;
+    async fn read_frame_inner(
+        &mut self,
+        write: &mut WriteHalf<S>,
+        deadline: Option<Instant>,
+    ) -> Result<Frame<'_>> {
+        if let Some(streamed) = self.streamed {
+            // The rest of a frame partially handed out in chunks.
+            self.ensure_read(write, streamed.remaining, deadline)
+                .await?;
+            self.streamed = None;
+            let data = &self.buffer[self.consumed..self.consumed + streamed.remaining];
+            self.consumed += streamed.remaining;
+            return Ok(Frame {
+                fin: streamed.fin,
+                opcode: Opcode::Continuation,
+                data,
+            });
+        }
+
+        let (fin, opcode, length) = self.read_header(write, deadline).await?;
+
+        let data = &self.buffer[self.consumed..self.consumed + length];
+        self.consumed += length;
+
+        Ok(Frame { fin, opcode, data })
+    }
+
+    /// Parses the header of the next frame and ensures that its payload is
+    /// buffered, returning whether it is final, its opcode and payload length.
+    ///
+    /// The header is only consumed once the whole frame has been buffered, so
+    /// that parsing restarts from the start of the frame if it is abandoned.
+    async fn read_header(
+        &mut self,
+        write: &mut WriteHalf<S>,
+        deadline: Option<Instant>,
+    ) -> Result<(bool, Opcode, usize)> {
+        let (fin, opcode, length, header_len) = self.parse_header(write, deadline).await?;
 
-        if self.consumed > 0 && self.buffer.len() > self.buffer.capacity() - Self::CHUNK_SIZE {
-            self.buffer.drain(..self.consumed);
-            self.consumed = 0;
+        // Reject oversized frames before growing the buffer to hold them.
+        if self.max_frame_size.is_some_and(|max| length > max) {
+            return Err(ProtocolViolation::MessageTooBig.into());
         }
 
-        self.ensure_read(HEADER_LEN).await?;
+        self.ensure_read(write, header_len + length, deadline)
+            .await?;
+
+        // Pings are only counted once received in full, as parsing may restart.
+        if matches!(opcode, Opcode::Ping) && self.ping_rate_exceeded() {
+            return Err(ProtocolViolation::PingRateExceeded.into());
+        }
+
+        self.consumed += header_len;
+        Ok((fin, opcode, length))
+    }
+
+    /// Parses and validates the header of the next frame without consuming
+    /// it, returning whether it is final, its opcode, payload length and the
+    /// length of the header.
+    async fn parse_header(
+        &mut self,
+        write: &mut WriteHalf<S>,
+        deadline: Option<Instant>,
+    ) -> Result<(bool, Opcode, usize, usize)> {
+        const HEADER_LEN: usize = 2;
+
+        self.ensure_read(write, HEADER_LEN, deadline).await?;
 
         let b1 = self.buffer[self.consumed];
         let b2 = self.buffer[self.consumed + 1];
-        self.consumed += HEADER_LEN;
+        let mut header_len = HEADER_LEN;
 
         let fin = b1 & 0x80 != 0;
         let rsv = b1 & 0x70;
@@ -336,12 +1115,10 @@ where
         let mut length = (b2 & 0x7F) as usize;
 
         if rsv != 0 {
-            return Err(Error::ProtocolViolation("Reserve bit must be 0."));
+            return Err(ProtocolViolation::ReservedBits.into());
         }
         if masked {
-            return Err(Error::ProtocolViolation(
-                "Server to client communication should be unmasked.",
-            ));
+            return Err(ProtocolViolation::MaskedFrame.into());
         }
 
         match opcode {
@@ -355,35 +1132,25 @@ where
             | Opcode::ReservedD
             | Opcode::ReservedE
             | Opcode::ReservedF => {
-                return Err(Error::ProtocolViolation("Use of reserved opcode."));
+                return Err(ProtocolViolation::ReservedOpcode.into());
             }
             Opcode::Close => {
                 if length == 1 {
-                    return Err(Error::ProtocolViolation(
-                        "Close frame with a missing close reason byte.",
-                    ));
+                    return Err(ProtocolViolation::InvalidClosePayload.into());
                 }
                 if length > 125 {
-                    return Err(Error::ProtocolViolation(
-                        "Control frame larger than 125 bytes.",
-                    ));
+                    return Err(ProtocolViolation::ControlFrameTooLarge.into());
                 }
                 if !fin {
-                    return Err(Error::ProtocolViolation(
-                        "Control frame cannot be fragmented.",
-                    ));
+                    return Err(ProtocolViolation::FragmentedControlFrame.into());
                 }
             }
             Opcode::Ping | Opcode::Pong => {
                 if length > 125 {
-                    return Err(Error::ProtocolViolation(
-                        "Control frame larger than 125 bytes.",
-                    ));
+                    return Err(ProtocolViolation::ControlFrameTooLarge.into());
                 }
                 if !fin {
-                    return Err(Error::ProtocolViolation(
-                        "Control frame cannot be fragmented.",
-                    ));
+                    return Err(ProtocolViolation::FragmentedControlFrame.into());
                 }
             }
             Opcode::Text | Opcode::Binary | Opcode::Continuation => {
@@ -391,66 +1158,282 @@ where
                     126 => {
                         const LENGTH_LEN: usize = 2;
 
-                        self.ensure_read(LENGTH_LEN).await?;
+                        self.ensure_read(write, header_len + LENGTH_LEN, deadline)
+                            .await?;
 
+                        let start = self.consumed + header_len;
                         let mut bytes = [0u8; LENGTH_LEN];
-                        bytes.copy_from_slice(
-                            &self.buffer[self.consumed..self.consumed + LENGTH_LEN],
-                        );
-                        self.consumed += LENGTH_LEN;
+                        bytes.copy_from_slice(&self.buffer[start..start + LENGTH_LEN]);
+                        header_len += LENGTH_LEN;
                         u16::from_be_bytes(bytes) as usize
                     }
                     127 => {
                         const LENGTH_LEN: usize = 8;
 
-                        self.ensure_read(LENGTH_LEN).await?;
+                        self.ensure_read(write, header_len + LENGTH_LEN, deadline)
+                            .await?;
 
+                        let start = self.consumed + header_len;
                         let mut bytes = [0u8; LENGTH_LEN];
-                        bytes.copy_from_slice(
-                            &self.buffer[self.consumed..self.consumed + LENGTH_LEN],
-                        );
-                        self.consumed += LENGTH_LEN;
-                        u64::from_be_bytes(bytes) as usize
+                        bytes.copy_from_slice(&self.buffer[start..start + LENGTH_LEN]);
+                        header_len += LENGTH_LEN;
+                        let length = u64::from_be_bytes(bytes);
+                        if length & (1 << 63) != 0 {
+                            return Err(ProtocolViolation::InvalidPayloadLength.into());
+                        }
+                        usize::try_from(length).map_err(|_| ProtocolViolation::MessageTooBig)?
                     }
                     length => length,
                 };
             }
         }
 
-        self.ensure_read(length).await?;
-
-        let data = &self.buffer[self.consumed..self.consumed + length];
-        self.consumed += length;
+        Ok((fin, opcode, length, header_len))
+    }
 
-        Ok(Frame { fin, opcode, data })
+    /// Counts a received ping, returning whether it exceeds the ping rate limit.
+    fn ping_rate_exceeded(&mut self) -> bool {
+        let Some(max_pings_per_second) = self.max_pings_per_second else {
+            return false;
+        };
+        let now = Instant::now();
+        if now.duration_since(self.ping_window_start) >= Duration::from_secs(1) {
+            self.ping_window_start = now;
+            self.ping_window_count = 0;
+        }
+        self.ping_window_count += 1;
+        self.ping_window_count > max_pings_per_second
     }
 
     #[inline]
-    async fn ensure_read(&mut self, len: usize) -> Result<()> {
+    async fn ensure_read(
+        &mut self,
+        write: &mut WriteHalf<S>,
+        len: usize,
+        deadline: Option<Instant>,
+    ) -> Result<()> {
         while self.buffer.len() < self.consumed + len {
-            let buffer = mem::take(&mut self.buffer);
-            let (res, buffer) = self.inner.read_extend(buffer, Self::CHUNK_SIZE).await;
-            self.buffer = buffer;
-            let _ = res?;
+            match self.read_more(write, deadline).await? {
+                // The peer closed the stream without a close frame.
+                Ok(0) => return Err(Error::AbnormalClosure),
+                Ok(_) => {}
+                // TLS reports a stream closed without close_notify as truncated.
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    return Err(Error::AbnormalClosure);
+                }
+                Err(e) => return Err(e.into()),
+            }
         }
         Ok(())
     }
+
+    /// Reads more data into the buffer, resuming the pending read if any.
+    async fn read_more(
+        &mut self,
+        write: &mut WriteHalf<S>,
+        deadline: Option<Instant>,
+    ) -> Result<io::Result<usize>> {
+        // Buffered frames are exhausted, so reply to the latest ping before
+        // waiting for more.
+        write.queue_pending_pong()?;
+        write.flush().await?;
+
+        let idle_deadline = self
+            .read_timeout
+            .map(|read_timeout| Instant::now() + read_timeout);
+        let deadline = match (deadline, idle_deadline) {
+            (Some(deadline), Some(idle_deadline)) => Some(deadline.min(idle_deadline)),
+            (deadline, idle_deadline) => deadline.or(idle_deadline),
+        };
+        let keepalive = self
+            .keepalive_interval
+            .filter(|_| write.state() == ConnectionState::Open)
+            .map(|interval| (self.last_received, interval, self.keepalive_timeout));
+
+        let read = match &mut self.pending_read {
+            Some(read) => read,
+            None => {
+                if self.consumed == self.buffer.len() {
+                    self.buffer.clear();
+                    self.consumed = 0;
+                } else if self.consumed > 0
+                    && self.buffer.len() > self.buffer.capacity().saturating_sub(Self::CHUNK_SIZE)
+                {
+                    self.buffer.drain(..self.consumed);
+                    self.consumed = 0;
+                }
+                let Some(mut inner) = self.inner.take() else {
+                    unreachable!("The read half is returned by a completed read.");
+                };
+                let buffer = mem::take(&mut self.buffer);
+                self.pending_read.insert(Box::pin(async move {
+                    let res = inner.read_extend(buffer, Self::CHUNK_SIZE).await;
+                    (inner, res)
+                }))
+            }
+        };
+        let (inner, (res, buffer)) =
+            wait_for_read(read.as_mut(), write, keepalive, deadline).await?;
+        self.end_read(inner, buffer);
+        self.last_received = Instant::now();
+        Ok(res)
+    }
+}
+
+/// Waits for `read` to complete until `deadline`. With `keepalive` given as
+/// the time data was last received, an interval and a timeout, sends a ping
+/// once nothing has been received for the interval unless one is outstanding
+/// already, and fails the connection if neither the ping has been answered
+/// nor data received within the timeout.
+async fn wait_for_read<S, F>(
+    mut read: Pin<&mut F>,
+    write: &mut WriteHalf<S>,
+    keepalive: Option<(Instant, Duration, Duration)>,
+    deadline: Option<Instant>,
+) -> Result<F::Output>
+where
+    S: AsyncWriteRent + 'static,
+    F: Future + ?Sized,
+{
+    let Some((last_received, interval, timeout)) = keepalive else {
+        return until(deadline, read).await.ok_or(Error::ReadTimeout);
+    };
+
+    loop {
+        let due = match write.keepalive_ping {
+            Some((_, sent)) => sent.max(last_received) + timeout,
+            None => last_received + interval,
+        };
+        if let Some(res) = until(Some(earliest(deadline, due)), read.as_mut()).await {
+            return Ok(res);
+        }
+        if deadline.is_some_and(|deadline| deadline < due) {
+            return Err(Error::ReadTimeout);
+        }
+        if write.keepalive_ping.is_some() {
+            // The peer is unresponsive, so don't wait on a closing handshake.
+            let _ = write.shutdown().await;
+            return Err(Error::KeepaliveTimeout);
+        }
+        let payload = write.rng.random::<u64>().to_ne_bytes();
+        write.queue_reply(Opcode::Ping, &payload)?;
+        write.keepalive_ping = Some((payload, Instant::now()));
+        write.flush().await?;
+    }
+}
+
+/// Awaits `future`, returning `None` if `deadline` passes first.
+async fn until<F: Future>(deadline: Option<Instant>, future: F) -> Option<F::Output> {
+    match deadline {
+        Some(deadline) => {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            monoio::time::timeout(timeout, future).await.ok()
+        }
+        None => Some(future.await),
+    }
+}
+
+fn earliest(deadline: Option<Instant>, instant: Instant) -> Instant {
+    deadline.map_or(instant, |deadline| deadline.min(instant))
 }
 
+/// A write of queued replies to the stream. It is parked in the write half
+/// while pending, owning the half and the replies until it completes, so that
+/// dropping the read which started it never leaves a reply partially written.
+type PendingWrite<S> =
+    Pin<Box<dyn Future<Output = (OwnedWriteHalf<S>, (io::Result<usize>, Vec<u8>))>>>;
+
 struct WriteHalf<S>
 where
     S: AsyncWriteRent,
 {
-    inner: OwnedWriteHalf<S>,
+    /// Taken by the pending write, if any, and dropped along with it once the
+    /// stream has been shut down.
+    inner: Option<OwnedWriteHalf<S>>,
+    pending_write: Option<PendingWrite<S>>,
+    /// Whether a write is in progress, which is only still the case before it
+    /// starts if a send has been dropped in the middle of writing.
+    writing: bool,
     rng: SmallRng,
     buffer: Vec<u8>,
+    /// Encoded replies to received frames, sent by the next flush.
+    replies: Vec<u8>,
+    close_sent: bool,
+    close_received: bool,
+    shutdown: bool,
+    /// Kind of the message whose non-final data frame has been sent without
+    /// the final one.
+    sending_message: Option<MessageKind>,
+    coalesce_pongs: bool,
+    pending_pong: Option<Vec<u8>>,
+    pong_policy: PongPolicy,
+    close_policy: ClosePolicy,
+    rtt: RttTracker,
+    /// Payload of the keepalive ping which has not been answered yet and when
+    /// it was sent.
+    keepalive_ping: Option<([u8; 8], Instant)>,
+    write_timeout: Option<Duration>,
+    max_send_frame_size: Option<usize>,
 }
 
 impl<S> WriteHalf<S>
 where
     S: AsyncWriteRent,
 {
-    pub async fn send_ping(&mut self, data: &[u8]) -> io::Result<()> {
+    /// Completes the pending write, if any, failing the connection if it
+    /// doesn't complete within [`Config::write_timeout`].
+    async fn finish_write(&mut self) -> Result<()> {
+        let Some(write) = self.pending_write.as_mut() else {
+            return Ok(());
+        };
+        let deadline = self
+            .write_timeout
+            .map(|write_timeout| Instant::now() + write_timeout);
+        let Some((inner, (res, mut replies))) = until(deadline, write).await else {
+            // The reply may have been partially written, so nothing else can
+            // be sent.
+            let _ = self.shutdown().await;
+            return Err(Error::WriteTimeout);
+        };
+        self.pending_write = None;
+        self.inner = Some(inner);
+        if self.replies.is_empty() {
+            // Reuse the buffer unless more replies have been queued meanwhile.
+            replies.clear();
+            self.replies = replies;
+        }
+        res.map(|_| ()).map_err(Into::into)
+    }
+
+    async fn shutdown(&mut self) -> Result<()> {
+        if !self.shutdown {
+            self.shutdown = true;
+            // Nothing more is going to be sent, so abandon a pending write
+            // rather than waiting for it.
+            self.pending_write = None;
+            if let Some(inner) = &mut self.inner {
+                inner.shutdown().await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S> WriteHalf<S>
+where
+    S: AsyncWriteRent + 'static,
+{
+    fn state(&self) -> ConnectionState {
+        if self.shutdown || self.writing || (self.close_sent && self.close_received) {
+            ConnectionState::Closed
+        } else if self.close_sent || self.close_received {
+            ConnectionState::Closing
+        } else {
+            ConnectionState::Open
+        }
+    }
+
+    pub async fn send_ping(&mut self, data: &[u8]) -> Result<()> {
         self.send(Frame {
             fin: true,
             opcode: Opcode::Ping,
@@ -459,7 +1442,7 @@ where
         .await
     }
 
-    pub async fn send_pong(&mut self, data: &[u8]) -> io::Result<()> {
+    pub async fn send_pong(&mut self, data: &[u8]) -> Result<()> {
         self.send(Frame {
             fin: true,
             opcode: Opcode::Pong,
@@ -468,51 +1451,697 @@ where
         .await
     }
 
-    pub async fn send_binary(&mut self, data: &[u8]) -> io::Result<()> {
-        self.send(Frame {
-            fin: true,
-            opcode: Opcode::Binary,
-            data,
-        })
-        .await
+    pub async fn send_binary(&mut self, data: &[u8]) -> Result<()> {
+        self.send_message(Opcode::Binary, data).await
     }
 
-    pub async fn send_text(&mut self, data: &[u8]) -> io::Result<()> {
+    pub async fn send_text(&mut self, data: &[u8]) -> Result<()> {
+        self.send_message(Opcode::Text, data).await
+    }
+
+    /// Sends a data message, fragmented if it exceeds
+    /// [`Config::max_send_frame_size`].
+    async fn send_message(&mut self, opcode: Opcode, data: &[u8]) -> Result<()> {
+        let Some(max) = self.max_send_frame_size.filter(|&max| data.len() > max) else {
+            return self
+                .send(Frame {
+                    fin: true,
+                    opcode,
+                    data,
+                })
+                .await;
+        };
+
+        let mut opcode = opcode;
+        let mut fragments = data.chunks(max.max(1)).peekable();
+        while let Some(fragment) = fragments.next() {
+            let res = self
+                .send(Frame {
+                    fin: fragments.peek().is_none(),
+                    opcode,
+                    data: fragment,
+                })
+                .await;
+            if res.is_err() && matches!(opcode, Opcode::Continuation) {
+                // Nothing else could be sent before finishing the message.
+                self.sending_message = None;
+                let _ = self.shutdown().await;
+            }
+            res?;
+            opcode = Opcode::Continuation;
+        }
+        Ok(())
+    }
+
+    pub async fn send_close(&mut self, data: &[u8]) -> Result<()> {
         self.send(Frame {
             fin: true,
-            opcode: Opcode::Text,
+            opcode: Opcode::Close,
             data,
         })
         .await
     }
 
-    pub async fn send_close(&mut self, data: &[u8]) -> io::Result<()> {
-        self.send(Frame {
+    /// Fails the connection, sending a close frame with `code` unless one has
+    /// already been sent, and shuts down the stream.
+    async fn fail(&mut self, code: CloseCode) -> Result<()> {
+        if !self.close_sent && !self.shutdown {
+            // Sent like a reply, as the connection is mostly failed while
+            // receiving.
+            self.queue_reply(Opcode::Close, &u16::from(code).to_be_bytes())?;
+            self.flush().await?;
+        }
+        self.shutdown().await
+    }
+
+    /// Fails the connection due to `violation`, returning the error to report.
+    async fn violation(&mut self, violation: ProtocolViolation) -> Error {
+        match self.fail(violation.close_code()).await {
+            Ok(()) => Error::ProtocolViolation(violation),
+            Err(e) => e,
+        }
+    }
+
+    /// Handles an error which occurred while reading, failing the connection
+    /// if the peer is at fault, and returns the error to report.
+    async fn read_failed(&mut self, e: Error) -> Error {
+        match e {
+            Error::ProtocolViolation(violation) => self.violation(violation).await,
+            Error::AbnormalClosure => {
+                // Nothing can be sent to a peer which has gone away.
+                let _ = self.shutdown().await;
+                Error::AbnormalClosure
+            }
+            e => e,
+        }
+    }
+
+    /// Queues the reply to a received ping as configured by
+    /// [`Config::pong_policy`].
+    fn reply_to_ping(&mut self, data: &[u8]) -> Result<()> {
+        // Nothing may be sent after a close frame.
+        if self.close_sent || self.shutdown {
+            return Ok(());
+        }
+        let mut pong = match &self.pong_policy {
+            PongPolicy::Automatic => Cow::Borrowed(data),
+            PongPolicy::Manual => return Ok(()),
+            PongPolicy::Callback(callback) => match callback(data) {
+                Some(pong) => Cow::Owned(pong),
+                None => return Ok(()),
+            },
+        };
+        if pong.len() > 125 {
+            pong.to_mut().truncate(125);
+        }
+
+        if self.coalesce_pongs {
+            // Replaces the pong for any earlier ping not yet replied to.
+            self.pending_pong = Some(pong.into_owned());
+            return Ok(());
+        }
+        self.queue_reply(Opcode::Pong, &pong)
+    }
+
+    /// Queues the reply to a received close frame as configured by
+    /// [`Config::close_policy`] unless a close frame has already been sent.
+    fn reply_to_close(&mut self, data: &[u8], frame: Option<&CloseFrame>) -> Result<()> {
+        self.close_received = true;
+        if self.close_sent {
+            return Ok(());
+        }
+        match &self.close_policy {
+            // Auto-send close with the same code as received.
+            ClosePolicy::Automatic => self.queue_reply(Opcode::Close, data),
+            ClosePolicy::Manual => Ok(()),
+            ClosePolicy::Callback(callback) => {
+                let mut payload = Vec::with_capacity(2 + CloseFrame::MAX_REASON_LEN);
+                if let Some(reply) = callback(frame) {
+                    reply.encode(&mut payload)?;
+                }
+                self.queue_reply(Opcode::Close, &payload)
+            }
+        }
+    }
+
+    /// Queues the pong deferred by [`Config::coalesce_pongs`], if any.
+    fn queue_pending_pong(&mut self) -> Result<()> {
+        match self.pending_pong.take() {
+            Some(data) if !self.close_sent && !self.shutdown => {
+                self.queue_reply(Opcode::Pong, &data)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Encodes a control frame replying to a received frame, to be sent by the
+    /// next flush. Replies are queued rather than written right away so that
+    /// none are lost if the read which received the frame is dropped before
+    /// writing them, and written by a parked write so that none are left
+    /// partially written if it is dropped while writing them.
+    fn queue_reply(&mut self, opcode: Opcode, data: &[u8]) -> Result<()> {
+        let frame = Frame {
             fin: true,
-            opcode: Opcode::Close,
+            opcode,
             data,
-        })
-        .await
+        };
+        self.begin_send(&frame)?;
+        let mut encoded = Vec::with_capacity(Frame::CONTROL_HEADER_LEN + data.len());
+        frame.encode_control(&mut encoded, self.rng.random::<u32>().to_ne_bytes());
+        self.replies.extend_from_slice(&encoded);
+        Ok(())
+    }
+
+    /// Sends the queued replies after completing the pending write, if any.
+    async fn flush(&mut self) -> Result<()> {
+        self.finish_write().await?;
+        if self.replies.is_empty() {
+            return Ok(());
+        }
+        if self.writing {
+            // A dropped send may have left a frame partially written, so
+            // nothing else can be sent.
+            let _ = self.shutdown().await;
+            return Err(Error::IncompleteWrite);
+        }
+        let Some(mut inner) = self.inner.take() else {
+            return Err(Error::AlreadyClosed);
+        };
+        let replies = mem::take(&mut self.replies);
+        self.pending_write = Some(Box::pin(async move {
+            let res = inner.write_all(replies).await;
+            (inner, res)
+        }));
+        self.finish_write().await
     }
 
     #[inline]
-    async fn send(&mut self, frame: Frame<'_>) -> io::Result<()> {
+    async fn send(&mut self, frame: Frame<'_>) -> Result<()> {
         self.write_frame(frame).await
     }
 
-    pub async fn write_frame(&mut self, frame: Frame<'_>) -> io::Result<()> {
+    /// Marks the frame as sent with respect to the closing handshake and the
+    /// message being sent. Nothing may be sent after a close frame, and data
+    /// frames must continue a fragmented message until it is finished.
+    #[inline]
+    fn begin_send(&mut self, frame: &Frame<'_>) -> Result<()> {
+        if self.close_sent || self.shutdown {
+            return Err(Error::AlreadyClosed);
+        }
+        let kind = match (frame.opcode, self.sending_message) {
+            (Opcode::Text, None) => MessageKind::Text,
+            (Opcode::Binary, None) => MessageKind::Binary,
+            (Opcode::Continuation, Some(kind)) => kind,
+            (Opcode::Text | Opcode::Binary | Opcode::Continuation, _) => {
+                return Err(Error::OutOfSequenceFrame);
+            }
+            (Opcode::Close, _) => {
+                self.close_sent = true;
+                return Ok(());
+            }
+            _ => return Ok(()),
+        };
+        self.sending_message = (!frame.fin).then_some(kind);
+        Ok(())
+    }
+
+    pub async fn write_frame(&mut self, frame: Frame<'_>) -> Result<()> {
+        // A deferred pong replies to a ping received before this frame, so is
+        // sent ahead of it rather than being lost to a close frame.
+        self.queue_pending_pong()?;
+        self.flush().await?;
+        self.begin_send(&frame)?;
         let mut dst = mem::take(&mut self.buffer);
         frame.encode(&mut dst, self.rng.random::<u32>().to_ne_bytes());
-        let (res, buffer) = self.inner.write_all(dst).await;
+        let (res, buffer) = self.write_encoded(dst).await;
         self.buffer = buffer;
-        res.map(|_| ())
+        res
     }
 
-    pub async fn write_control_frame(&mut self, frame: Frame<'_>) -> io::Result<()> {
-        let mut dst = mem::take(&mut self.buffer);
-        frame.encode_control(&mut dst, self.rng.random::<u32>().to_ne_bytes());
-        let (res, buffer) = self.inner.write_all(dst).await;
-        self.buffer = buffer;
-        res.map(|_| ())
+    /// Writes an encoded frame to the stream, returning the buffer for reuse.
+    async fn write_encoded(&mut self, dst: Vec<u8>) -> BufResult<()> {
+        if self.writing {
+            // A dropped send may have left a frame partially written, so
+            // nothing else can be sent.
+            let _ = self.shutdown().await;
+            return (Err(Error::IncompleteWrite), dst);
+        }
+        let Some(inner) = &mut self.inner else {
+            return (Err(Error::AlreadyClosed), dst);
+        };
+        let deadline = self
+            .write_timeout
+            .map(|write_timeout| Instant::now() + write_timeout);
+        self.writing = true;
+        let Some((res, buffer)) = until(deadline, inner.write_all(dst)).await else {
+            // The frame may have been partially written, so nothing else can
+            // be sent.
+            let _ = self.shutdown().await;
+            return (Err(Error::WriteTimeout), Vec::new());
+        };
+        self.writing = false;
+        (res.map(|_| ()).map_err(Into::into), buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::*;
+    use crate::test_util::{answer_pings, exchange, receive, serve};
+
+    #[monoio::test(timer_enabled = true)]
+    async fn test_dropped_read_is_resumed() {
+        // Split a text frame in two, so that the first read is dropped with
+        // only part of it received.
+        let mut client = serve(&[b"\x81\x05he", b"llo"], &Config::default()).await;
+
+        monoio::select! {
+            _ = client.read_frame() => panic!("frame received before it was sent in full"),
+            () = monoio::time::sleep(Duration::from_millis(50)) => {}
+        }
+
+        let frame = client.read_frame().await.unwrap();
+        assert!(matches!(frame.opcode, Opcode::Text));
+        assert_eq!(frame.data, b"hello");
+    }
+
+    #[monoio::test(timer_enabled = true)]
+    async fn test_into_parts_after_timeout() {
+        // A text frame followed by another one arriving in two parts.
+        let mut client = serve(&[b"\x81\x02hi\x81\x05hel", b"lo"], &Config::default()).await;
+
+        assert_eq!(client.read_frame().await.unwrap().data, b"hi");
+        let deadline = Instant::now() + Duration::from_millis(50);
+        assert!(matches!(
+            client.read_frame_until(deadline).await,
+            Err(Error::ReadTimeout)
+        ));
+
+        // The read left pending by the timeout receives the rest.
+        let (_stream, leftover) = client.into_parts().await.unwrap();
+        assert_eq!(leftover, b"\x81\x05hello");
+    }
+
+    #[monoio::test(timer_enabled = true)]
+    async fn test_into_parts_while_receiving() {
+        // The first fragment of a message.
+        let mut client = serve(&[b"\x01\x02he"], &Config::default()).await;
+        let (res, _) = client
+            .next_msg_timeout(Duration::from_millis(50), Vec::new())
+            .await;
+        assert!(matches!(res, Err(Error::ReadTimeout)));
+
+        let e = client.into_parts().await.unwrap_err();
+        assert!(matches!(e.error(), Error::MessageInProgress));
+    }
+
+    #[monoio::test(timer_enabled = true)]
+    async fn test_from_upgraded() {
+        // The rest of a frame which started in the bytes read past the
+        // handshake response.
+        let client = serve(&[b"\x01!"], &Config::default()).await;
+        let (stream, leftover) = client.into_parts().await.unwrap();
+        assert_eq!(leftover, b"");
+
+        let mut client = Client::from_upgraded(stream, b"\x81\x02hi\x82", &Config::default());
+        assert_eq!(client.recv().await.unwrap(), Message::Text("hi".to_owned()));
+        assert_eq!(client.recv().await.unwrap(), Message::Binary(b"!".to_vec()));
+    }
+
+    #[monoio::test(timer_enabled = true)]
+    async fn test_dropped_read_while_replying() {
+        // Enough pings for the pongs to fill up the socket buffers, as the
+        // server never reads.
+        let pings = [b"\x89\x7D".as_slice(), &[0; 125]].concat().repeat(1 << 16);
+        let mut client = serve(&[&pings], &Config::default()).await;
+
+        loop {
+            monoio::select! {
+                res = client.read_frame() => assert!(matches!(res.unwrap().opcode, Opcode::Ping)),
+                () = monoio::time::sleep(Duration::from_millis(200)) => break,
+            }
+        }
+
+        // The pong left partially written is kept rather than failing the
+        // connection.
+        assert_eq!(client.state(), ConnectionState::Open);
+    }
+
+    #[monoio::test(timer_enabled = true)]
+    async fn test_dropped_send_closes_connection() {
+        // The server never reads, so that a large frame can't be written in
+        // full.
+        let mut client = serve(&[], &Config::default()).await;
+
+        let data = vec![0; 64 << 20];
+        monoio::select! {
+            _ = client.send_binary(&data) => panic!("frame written without being read"),
+            () = monoio::time::sleep(Duration::from_millis(50)) => {}
+        }
+
+        assert_eq!(client.state(), ConnectionState::Closed);
+        assert!(matches!(
+            client.send_ping(b"").await,
+            Err(Error::IncompleteWrite)
+        ));
+    }
+
+    #[monoio::test]
+    async fn test_automatic_fragmentation() {
+        let config = Config {
+            max_send_frame_size: Some(2),
+            ..Config::default()
+        };
+        let (mut client, server) = receive(4, &config).await;
+        client.send_binary(b"hello").await.unwrap();
+        client.send_binary(b"hi").await.unwrap();
+
+        assert_eq!(
+            server.await,
+            [
+                (0x02, b"he".to_vec()),
+                (0x00, b"ll".to_vec()),
+                (0x80, b"o".to_vec()),
+                (0x82, b"hi".to_vec()),
+            ]
+        );
+    }
+
+    #[monoio::test]
+    async fn test_send_text_bytes() {
+        let config = Config {
+            max_send_frame_size: Some(3),
+            ..Config::default()
+        };
+        let (mut client, server) = receive(2, &config).await;
+        client.send_text_bytes("héllo".as_bytes()).await.unwrap();
+
+        // Fragmented like any other message.
+        assert_eq!(
+            server.await,
+            [(0x01, b"h\xC3\xA9".to_vec()), (0x80, b"llo".to_vec())]
+        );
+    }
+
+    #[monoio::test(timer_enabled = true)]
+    async fn test_failed_fragment_closes_connection() {
+        let config = Config {
+            max_send_frame_size: Some(64 << 10),
+            write_timeout: Some(Duration::from_millis(50)),
+            ..Config::default()
+        };
+        // The server never reads, so that the fragments can't be written in
+        // full.
+        let mut client = serve(&[], &config).await;
+
+        let data = vec![0; 64 << 20];
+        assert!(matches!(
+            client.send_binary(&data).await,
+            Err(Error::WriteTimeout)
+        ));
+        assert_eq!(client.sending_message(), None);
+        assert_eq!(client.state(), ConnectionState::Closed);
+    }
+
+    #[test_case(b"\x81\x04abcd", CloseCode::MessageTooBig; "frame too big")]
+    #[test_case(b"\x01\x02ab\x80\x02cd", CloseCode::MessageTooBig; "message too big")]
+    #[test_case(b"\x82\x7F\x80\0\0\0\0\0\0\0", CloseCode::ProtocolError; "length with top bit set")]
+    #[monoio::test(timer_enabled = true)]
+    async fn test_size_limits(data: &[u8], code: CloseCode) {
+        let config = Config {
+            max_frame_size: Some(3),
+            max_message_size: Some(3),
+            ..Config::default()
+        };
+        let (mut client, server) = exchange(&[data], 1, &config).await;
+
+        let e = client.recv().await.unwrap_err();
+        assert_eq!(e.close_code(), Some(code));
+        assert_eq!(client.state(), ConnectionState::Closed);
+        let code = u16::from(code).to_be_bytes().to_vec();
+        assert_eq!(server.await, [(0x88, code)]);
+    }
+
+    #[monoio::test(timer_enabled = true)]
+    async fn test_ping_flood() {
+        let config = Config {
+            max_pings_per_second: Some(2),
+            ..Config::default()
+        };
+        let (mut client, server) = exchange(&[b"\x89\x00\x89\x00\x89\x00"], 3, &config).await;
+
+        let e = client.recv().await.unwrap_err();
+        assert_eq!(e.close_code(), Some(CloseCode::PolicyViolation));
+        assert_eq!(
+            server.await,
+            [
+                (0x8A, Vec::new()),
+                (0x8A, Vec::new()),
+                (0x88, b"\x03\xF0".to_vec()),
+            ]
+        );
+    }
+
+    #[test_case(b"\x01\x02ab\x00\x02cd\x80\x02ef"; "too many fragments")]
+    #[test_case(b"\x01\x01a\x80\x02bc"; "fragment too small")]
+    #[monoio::test(timer_enabled = true)]
+    async fn test_fragment_limits(data: &[u8]) {
+        let config = Config {
+            max_fragments: Some(2),
+            min_fragment_size: Some(2),
+            ..Config::default()
+        };
+        let (mut client, server) = exchange(&[data], 1, &config).await;
+
+        let e = client.recv().await.unwrap_err();
+        assert_eq!(e.close_code(), Some(CloseCode::PolicyViolation));
+        assert_eq!(server.await, [(0x88, b"\x03\xF0".to_vec())]);
+    }
+
+    #[monoio::test(timer_enabled = true)]
+    async fn test_coalesced_pongs() {
+        let config = Config {
+            coalesce_pongs: true,
+            ..Config::default()
+        };
+        let (mut client, server) = exchange(&[b"\x89\x01a\x89\x01b\x81\x02hi"], 2, &config).await;
+
+        assert_eq!(client.recv().await.unwrap(), Message::Text("hi".to_owned()));
+        client.send_close(None).await.unwrap();
+        // Only the latest ping is replied to.
+        assert_eq!(server.await, [(0x8A, b"b".to_vec()), (0x88, Vec::new())]);
+    }
+
+    #[test_case(PongPolicy::Manual, None; "manual")]
+    #[test_case(PongPolicy::Callback(Rc::new(|_| None)), None; "callback without reply")]
+    #[test_case(
+        PongPolicy::Callback(Rc::new(|data| Some([data, b"!"].concat()))),
+        Some(b"hi!".as_slice());
+        "callback"
+    )]
+    #[monoio::test(timer_enabled = true)]
+    async fn test_pong_policy(pong_policy: PongPolicy, expected: Option<&[u8]>) {
+        let config = Config {
+            pong_policy,
+            ..Config::default()
+        };
+        let count = 1 + usize::from(expected.is_some());
+        let (mut client, server) = exchange(&[b"\x89\x02hi\x81\x02ok"], count, &config).await;
+
+        assert_eq!(client.recv().await.unwrap(), Message::Text("ok".to_owned()));
+        client.send_close(None).await.unwrap();
+        let mut frames: Vec<_> = expected
+            .map(|pong| (0x8A, pong.to_vec()))
+            .into_iter()
+            .collect();
+        frames.push((0x88, Vec::new()));
+        assert_eq!(server.await, frames);
+    }
+
+    #[monoio::test(timer_enabled = true)]
+    async fn test_manual_close_policy() {
+        let config = Config {
+            close_policy: ClosePolicy::Manual,
+            ..Config::default()
+        };
+        let (mut client, server) = exchange(&[b"\x88\x02\x03\xE8"], 1, &config).await;
+
+        assert!(matches!(client.recv().await, Err(Error::Closed(_))));
+        // Nothing has been replied, leaving the reply up to the application.
+        assert_eq!(client.state(), ConnectionState::Closing);
+        client
+            .send_close(Some(&CloseFrame::new(CloseCode::GoingAway, "")))
+            .await
+            .unwrap();
+        assert_eq!(client.state(), ConnectionState::Closed);
+        assert_eq!(server.await, [(0x88, b"\x03\xE9".to_vec())]);
+    }
+
+    #[monoio::test(timer_enabled = true)]
+    async fn test_close_policy_callback() {
+        let config = Config {
+            close_policy: ClosePolicy::Callback(Rc::new(|frame| {
+                assert_eq!(frame, Some(&CloseFrame::new(CloseCode::Normal, "")));
+                Some(CloseFrame::new(CloseCode::GoingAway, "bye"))
+            })),
+            ..Config::default()
+        };
+        let (mut client, server) = exchange(&[b"\x88\x02\x03\xE8"], 1, &config).await;
+
+        assert!(matches!(client.recv().await, Err(Error::Closed(_))));
+        assert_eq!(client.state(), ConnectionState::Closed);
+        assert_eq!(server.await, [(0x88, b"\x03\xE9bye".to_vec())]);
+    }
+
+    #[test_case(b"\x81\x05he"; "in payload")]
+    #[test_case(b"\x82\x7E\x01"; "in header")]
+    #[test_case(b""; "between frames")]
+    #[monoio::test(timer_enabled = true)]
+    async fn test_eof(data: &[u8]) {
+        // The server closes the connection right after sending the data.
+        let (mut client, server) = exchange(&[data], 0, &Config::default()).await;
+        server.await;
+
+        let e = client.recv().await.unwrap_err();
+        assert!(matches!(e, Error::AbnormalClosure));
+        assert_eq!(e.close_code(), Some(CloseCode::Abnormal));
+        assert_eq!(client.state(), ConnectionState::Closed);
+    }
+
+    #[monoio::test(timer_enabled = true)]
+    async fn test_invalid_utf8_fails_fast() {
+        // The first fragment of a text message isn't valid UTF-8 and the rest
+        // never arrives.
+        let (mut client, server) = exchange(&[b"\x01\x02\xFF\xFE"], 1, &Config::default()).await;
+
+        let (res, _) = client
+            .next_msg_timeout(Duration::from_secs(1), Vec::new())
+            .await;
+        assert!(matches!(
+            res,
+            Err(Error::ProtocolViolation(ProtocolViolation::InvalidUtf8))
+        ));
+        assert_eq!(server.await, [(0x88, b"\x03\xEF".to_vec())]);
+    }
+
+    #[monoio::test(timer_enabled = true)]
+    async fn test_ping_while_closing() {
+        // The peer sends a ping before replying to the close frame.
+        let mut client = serve(&[b"\x89\x00\x88\x02\x03\xE8"], &Config::default()).await;
+        client.close(CloseCode::Normal, "").await.unwrap();
+        assert_eq!(client.state(), ConnectionState::Closed);
+    }
+
+    #[monoio::test(timer_enabled = true)]
+    async fn test_close_timeout() {
+        let config = Config {
+            close_timeout: Some(Duration::from_millis(50)),
+            ..Config::default()
+        };
+        // The peer never replies to the close frame.
+        let mut client = serve(&[], &config).await;
+        let start = Instant::now();
+        client.close(CloseCode::Normal, "").await.unwrap();
+        assert!(start.elapsed() < Duration::from_millis(500));
+        assert_eq!(client.state(), ConnectionState::Closed);
+    }
+
+    #[monoio::test(timer_enabled = true)]
+    async fn test_keepalive() {
+        let config = Config {
+            keepalive_interval: Some(Duration::from_millis(50)),
+            keepalive_timeout: Duration::from_millis(100),
+            ..Config::default()
+        };
+        let mut client = answer_pings(1, &config).await;
+
+        // Reads giving up before the interval passes still send the pings, of
+        // which only the first is answered.
+        let mut pongs = 0;
+        let e = loop {
+            let deadline = Instant::now() + Duration::from_millis(20);
+            match client.read_frame_until(deadline).await {
+                Ok(frame) => {
+                    assert!(matches!(frame.opcode, Opcode::Pong));
+                    pongs += 1;
+                }
+                Err(Error::ReadTimeout) => {}
+                Err(e) => break e,
+            }
+        };
+        assert_eq!(pongs, 1);
+        assert!(matches!(e, Error::KeepaliveTimeout));
+    }
+
+    #[monoio::test(timer_enabled = true)]
+    async fn test_keepalive_while_receiving() {
+        let config = Config {
+            keepalive_interval: Some(Duration::from_millis(20)),
+            keepalive_timeout: Duration::from_millis(150),
+            ..Config::default()
+        };
+        // Messages arriving one after another without the keepalive ping ever
+        // being answered.
+        let mut client = serve(&[b"", b"\x81\x01a", b"\x81\x01b", b"\x81\x01c"], &config).await;
+
+        for text in ["a", "b", "c"] {
+            assert_eq!(client.recv().await.unwrap(), Message::Text(text.to_owned()));
+        }
+        assert!(matches!(client.recv().await, Err(Error::KeepaliveTimeout)));
+    }
+
+    #[monoio::test(timer_enabled = true)]
+    async fn test_events() {
+        // A ping, a pong, a text message and a close frame with code 1000.
+        let mut client = serve(
+            &[b"\x89\x02hi\x8A\x00\x81\x02ok\x88\x02\x03\xE8"],
+            &Config::default(),
+        )
+        .await;
+        let mut events = Vec::new();
+        let mut buffer = Vec::new();
+        loop {
+            let (res, buf) = client.next_event(buffer).await;
+            let event = res.unwrap();
+            events.push((event.clone(), buf.clone()));
+            buffer = buf;
+            if matches!(event, Event::Close(_)) {
+                break;
+            }
+        }
+
+        assert_eq!(
+            events,
+            [
+                (Event::Ping, b"hi".to_vec()),
+                (Event::Pong, Vec::new()),
+                (Event::Text, b"ok".to_vec()),
+                (
+                    Event::Close(Some(CloseFrame::new(CloseCode::Normal, ""))),
+                    Vec::new()
+                ),
+            ]
+        );
+    }
+
+    #[monoio::test(timer_enabled = true)]
+    async fn test_close_event_without_code() {
+        let mut client = serve(&[b"\x88\x00"], &Config::default()).await;
+        let (res, _) = client.next_event(Vec::new()).await;
+        assert_eq!(res.unwrap(), Event::Close(None));
+    }
+
+    #[monoio::test(timer_enabled = true)]
+    async fn test_recv_ref() {
+        // A single frame text message followed by a fragmented binary one.
+        let mut client = serve(&[b"\x81\x02hi\x02\x01a\x80\x01b"], &Config::default()).await;
+
+        let message = client.recv_ref().await.unwrap();
+        assert_eq!(message.as_text(), Some("hi"));
+        let message = client.recv_ref().await.unwrap();
+        assert!(message.kind().is_binary());
+        assert_eq!(message.data(), b"ab");
     }
 }
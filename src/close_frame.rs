@@ -0,0 +1,144 @@
+use crate::{CloseCode, CloseCodeParseError, Frame};
+
+/// Payload of a close frame as defined in RFC 6455:
+/// <https://datatracker.ietf.org/doc/html/rfc6455#section-5.5.1>
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloseFrame {
+    pub code: CloseCode,
+    pub reason: String,
+}
+
+impl CloseFrame {
+    /// Maximum length of the reason in bytes, such that the close code and the
+    /// reason fit into the 125 byte payload of a control frame.
+    pub const MAX_REASON_LEN: usize = 123;
+
+    /// Creates a close frame, truncating `reason` to at most
+    /// [`CloseFrame::MAX_REASON_LEN`] bytes on a UTF-8 character boundary.
+    #[must_use]
+    pub fn new(code: CloseCode, reason: &str) -> Self {
+        Self {
+            code,
+            reason: truncate_reason(reason).to_owned(),
+        }
+    }
+
+    /// Appends the payload of the close frame to `dst`. Codes which must not
+    /// be sent by an endpoint are rejected.
+    pub fn encode(&self, dst: &mut Vec<u8>) -> Result<(), CloseFrameError> {
+        if self.code.is_reserved() {
+            return Err(CloseFrameError::ReservedCode(self.code));
+        }
+        let reason = truncate_reason(&self.reason);
+        dst.reserve(2 + reason.len());
+        dst.extend_from_slice(&u16::from(self.code).to_be_bytes());
+        dst.extend_from_slice(reason.as_bytes());
+        Ok(())
+    }
+
+    /// Decodes the payload of a received close frame. Returns `None` if the
+    /// payload is empty, meaning that no close code was given.
+    pub fn decode(data: &[u8]) -> Result<Option<Self>, CloseFrameError> {
+        match data {
+            [] => Ok(None),
+            [_] => Err(CloseFrameError::MissingCodeByte),
+            [high, low, reason @ ..] => {
+                let code = CloseCode::try_from(u16::from_be_bytes([*high, *low]))?;
+                if code.is_reserved() {
+                    return Err(CloseFrameError::ReservedCode(code));
+                }
+                // Everything after close code is a utf-8 reason string.
+                let reason = Frame::validate_utf8(reason).ok_or(CloseFrameError::InvalidUtf8)?;
+                Ok(Some(Self {
+                    code,
+                    reason: reason.to_owned(),
+                }))
+            }
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CloseFrameError {
+    #[error("Close frame with a missing close code byte.")]
+    MissingCodeByte,
+    #[error("{0}")]
+    InvalidCode(#[from] CloseCodeParseError),
+    #[error("Close code {0:?} must not be sent in a close frame.")]
+    ReservedCode(CloseCode),
+    #[error("Close frame with invalid utf-8 reason.")]
+    InvalidUtf8,
+}
+
+fn truncate_reason(reason: &str) -> &str {
+    if reason.len() <= CloseFrame::MAX_REASON_LEN {
+        return reason;
+    }
+    let mut end = CloseFrame::MAX_REASON_LEN;
+    while !reason.is_char_boundary(end) {
+        end -= 1;
+    }
+    &reason[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::*;
+
+    #[test_case(CloseCode::Normal, "", &[0x03, 0xE8]; "normal without reason")]
+    #[test_case(CloseCode::GoingAway, "bye", &[0x03, 0xE9, b'b', b'y', b'e']; "going away with reason")]
+    #[test_case(CloseCode::Private(4001), "", &[0x0F, 0xA1]; "private")]
+    fn test_encode(code: CloseCode, reason: &str, expected: &[u8]) {
+        let mut dst = Vec::new();
+        CloseFrame::new(code, reason).encode(&mut dst).unwrap();
+        assert_eq!(dst, expected);
+    }
+
+    #[test_case(CloseCode::Reserved; "reserved")]
+    #[test_case(CloseCode::NoStatusReceived; "no status received")]
+    #[test_case(CloseCode::Abnormal; "abnormal")]
+    fn test_encode_reserved(code: CloseCode) {
+        let mut dst = Vec::new();
+        assert!(matches!(
+            CloseFrame::new(code, "").encode(&mut dst),
+            Err(CloseFrameError::ReservedCode(_))
+        ));
+        assert!(dst.is_empty());
+    }
+
+    #[test]
+    fn test_truncate_reason() {
+        // 61 two-byte characters followed by a three-byte one crossing the limit.
+        let reason = format!("{}€", "é".repeat(61));
+        let frame = CloseFrame::new(CloseCode::Normal, &reason);
+        assert_eq!(frame.reason, "é".repeat(61));
+
+        let mut dst = Vec::new();
+        CloseFrame {
+            code: CloseCode::Normal,
+            reason: "a".repeat(200),
+        }
+        .encode(&mut dst)
+        .unwrap();
+        assert_eq!(dst.len(), 125);
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(CloseFrame::decode(&[]).unwrap(), None);
+        assert_eq!(
+            CloseFrame::decode(&[0x03, 0xE9, b'b', b'y', b'e']).unwrap(),
+            Some(CloseFrame::new(CloseCode::GoingAway, "bye"))
+        );
+    }
+
+    #[test_case(&[0x03]; "missing code byte")]
+    #[test_case(&[0x03, 0xE7]; "invalid code")]
+    #[test_case(&[0x03, 0xED]; "reserved code")]
+    #[test_case(&[0x03, 0xE8, 0xFF]; "invalid utf8 reason")]
+    fn test_decode_invalid(data: &[u8]) {
+        assert!(CloseFrame::decode(data).is_err());
+    }
+}
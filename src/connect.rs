@@ -1,17 +1,23 @@
 use std::{io, result, sync::Arc};
 
 use base64::{Engine, prelude::BASE64_STANDARD};
-use http::Uri;
+use http::{
+    HeaderName, HeaderValue, Request, Uri,
+    header::{
+        CONNECTION, COOKIE, HOST, ORIGIN, SEC_WEBSOCKET_EXTENSIONS, SEC_WEBSOCKET_KEY,
+        SEC_WEBSOCKET_PROTOCOL, SEC_WEBSOCKET_VERSION, UPGRADE, USER_AGENT,
+    },
+};
 use monoio::{
     io::{AsyncReadRent, AsyncReadRentExt, AsyncWriteRent, AsyncWriteRentExt},
     net::TcpStream,
 };
-use monoio_rustls::{Stream, TlsConnector, TlsError};
+use monoio_rustls::{ClientTlsStream, TlsConnector, TlsError};
 use rand::Rng;
-use rustls::{ClientConfig, ClientConnection, pki_types::InvalidDnsNameError};
+use rustls::{ClientConfig, pki_types::InvalidDnsNameError};
 use sha1::{Digest, Sha1};
 
-use crate::{Client, Config};
+use crate::{Client, Config, CookieJar, MaybeTlsStream};
 
 #[derive(Debug, thiserror::Error)]
 pub enum ConnectError {
@@ -27,62 +33,190 @@ pub enum ConnectError {
     InvalidDnsName(#[from] InvalidDnsNameError),
     #[error("Attempted to connect with invalid URI scheme")]
     InvalidUriScheme,
+    #[error("Invalid redirect location in handshake response: {0}")]
+    InvalidRedirectLocation(String),
+    #[cfg(feature = "h2")]
+    #[error("HTTP/2: {0}")]
+    Http2(#[from] h2::Error),
+    #[cfg(feature = "h2")]
+    #[error("The server did not negotiate HTTP/2 with ALPN")]
+    Http2NotNegotiated,
 }
 
 pub type ConnectResult<T> = result::Result<T, ConnectError>;
 
-impl Client<Stream<TcpStream, ClientConnection>> {
-    pub async fn connect_tls(uri: &Uri, config: &Config) -> ConnectResult<Self> {
-        if uri.scheme_str() != Some("wss") {
-            return Err(ConnectError::InvalidUriScheme);
-        }
-
-        let mut root_store = rustls::RootCertStore::empty();
-        root_store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
-
-        let tls_config = ClientConfig::builder()
-            .with_root_certificates(root_store)
-            .with_no_client_auth();
+impl Client<MaybeTlsStream> {
+    /// Connects to either a `ws` or a `wss` URI, upgrading to TLS as required
+    /// by the scheme. Redirects may switch from `ws` to `wss`, but not back.
+    pub async fn connect(uri: &Uri, config: &Config) -> ConnectResult<Self> {
+        Self::connect_request(upgrade_request(uri), config).await
+    }
 
-        let connector = TlsConnector::from(Arc::new(tls_config));
-        let server_name =
-            rustls::pki_types::ServerName::try_from(uri.host().unwrap_or_default().to_string())?;
+    /// Like [`Client::connect`], but takes the method, URI and any additional
+    /// headers of the upgrade request from `request`. The WebSocket specific
+    /// headers are filled in by the library.
+    ///
+    /// Redirects to another origin keep only the `Origin`, `User-Agent`,
+    /// `Sec-WebSocket-Protocol` and `Sec-WebSocket-Extensions` headers, so
+    /// that credentials such as `Authorization` or API keys don't leak.
+    pub async fn connect_request(request: Request<()>, config: &Config) -> ConnectResult<Self> {
+        let stream = connect_with(request, config, async |uri: &Uri| match uri.scheme_str() {
+            Some("ws") => Ok(MaybeTlsStream::Plain(tcp_connect(uri, 80).await?)),
+            Some("wss") => Ok(MaybeTlsStream::Tls(tls_connect(uri, Vec::new()).await?)),
+            _ => Err(ConnectError::InvalidUriScheme),
+        })
+        .await?;
+        Ok(Self::new(stream, config))
+    }
+}
 
+impl Client<ClientTlsStream<TcpStream>> {
+    pub async fn connect_tls(uri: &Uri, config: &Config) -> ConnectResult<Self> {
         // Connect, upgrade to TLS and perform WebSocket handshake.
-        let stream = TcpStream::connect(format!(
-            "{}:{}",
-            uri.host().unwrap_or_default(),
-            uri.port_u16().unwrap_or(443)
-        ))
+        let stream = connect_with(upgrade_request(uri), config, async |uri: &Uri| {
+            if uri.scheme_str() != Some("wss") {
+                return Err(ConnectError::InvalidUriScheme);
+            }
+            tls_connect(uri, Vec::new()).await
+        })
         .await?;
-        TcpStream::set_nodelay(&stream, true)?;
-
-        let stream = connector.connect(server_name, stream).await?;
-        Ok(Self::new(handshake(stream, uri).await?, config))
+        Ok(Self::new(stream, config))
     }
 }
 
 impl Client<TcpStream> {
     pub async fn connect_plain(uri: &Uri, config: &Config) -> ConnectResult<Self> {
-        if uri.scheme_str() != Some("ws") {
-            return Err(ConnectError::InvalidUriScheme);
-        }
-
         // Connect and perform WebSocket handshake.
-        let stream = TcpStream::connect(format!(
-            "{}:{}",
-            uri.host().unwrap_or_default(),
-            uri.port_u16().unwrap_or(80)
-        ))
+        let stream = connect_with(upgrade_request(uri), config, async |uri: &Uri| {
+            if uri.scheme_str() != Some("ws") {
+                return Err(ConnectError::InvalidUriScheme);
+            }
+            tcp_connect(uri, 80).await
+        })
         .await?;
-        TcpStream::set_nodelay(&stream, true)?;
+        Ok(Self::new(stream, config))
+    }
+}
 
-        Ok(Self::new(handshake(stream, uri).await?, config))
+/// Opens a stream via `open` and performs the WebSocket handshake on it,
+/// reopening a new stream for each redirect followed.
+async fn connect_with<T>(
+    mut request: Request<()>,
+    config: &Config,
+    open: impl AsyncFn(&Uri) -> ConnectResult<T>,
+) -> ConnectResult<T>
+where
+    T: AsyncReadRent + AsyncWriteRent,
+{
+    let mut redirects = 0;
+    loop {
+        let stream = open(request.uri()).await?;
+        let follow_redirects = redirects < config.max_redirects;
+        match handshake(
+            stream,
+            &request,
+            config.cookie_jar.as_deref(),
+            follow_redirects,
+        )
+        .await?
+        {
+            Handshake::Upgraded(stream) => return Ok(stream),
+            Handshake::Redirect(location) => {
+                redirects += 1;
+                redirect_request(&mut request, location);
+            }
+        }
     }
 }
 
+/// Points `request` to the `location` it has been redirected to.
+fn redirect_request(request: &mut Request<()>, location: Uri) {
+    /// Headers which are safe to send to another origin.
+    const CROSS_ORIGIN_HEADERS: [HeaderName; 4] = [
+        ORIGIN,
+        USER_AGENT,
+        SEC_WEBSOCKET_PROTOCOL,
+        SEC_WEBSOCKET_EXTENSIONS,
+    ];
+
+    if !same_origin(request.uri(), &location) {
+        let names: Vec<HeaderName> = request
+            .headers()
+            .keys()
+            .filter(|name| !CROSS_ORIGIN_HEADERS.contains(name))
+            .cloned()
+            .collect();
+        for name in names {
+            request.headers_mut().remove(name);
+        }
+    }
+    *request.uri_mut() = location;
+    // The host header would otherwise still point to the previous host.
+    request.headers_mut().remove(HOST);
+}
+
+fn same_origin(a: &Uri, b: &Uri) -> bool {
+    let port = |uri: &Uri| {
+        uri.port_u16().or(match uri.scheme_str() {
+            Some("ws") => Some(80),
+            Some("wss") => Some(443),
+            _ => None,
+        })
+    };
+    a.scheme() == b.scheme()
+        && a.host().map(str::to_ascii_lowercase) == b.host().map(str::to_ascii_lowercase)
+        && port(a) == port(b)
+}
+
+pub(crate) fn upgrade_request(uri: &Uri) -> Request<()> {
+    let mut request = Request::new(());
+    *request.uri_mut() = uri.clone();
+    request
+}
+
+pub(crate) async fn tcp_connect(uri: &Uri, default_port: u16) -> ConnectResult<TcpStream> {
+    let stream = TcpStream::connect(format!(
+        "{}:{}",
+        uri.host().unwrap_or_default(),
+        uri.port_u16().unwrap_or(default_port)
+    ))
+    .await?;
+    TcpStream::set_nodelay(&stream, true)?;
+    Ok(stream)
+}
+
+pub(crate) async fn tls_connect(
+    uri: &Uri,
+    alpn_protocols: Vec<Vec<u8>>,
+) -> ConnectResult<ClientTlsStream<TcpStream>> {
+    let mut root_store = rustls::RootCertStore::empty();
+    root_store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+
+    let mut tls_config = ClientConfig::builder()
+        .with_root_certificates(root_store)
+        .with_no_client_auth();
+    tls_config.alpn_protocols = alpn_protocols;
+
+    let connector = TlsConnector::from(Arc::new(tls_config));
+    let server_name =
+        rustls::pki_types::ServerName::try_from(uri.host().unwrap_or_default().to_string())?;
+
+    let stream = tcp_connect(uri, 443).await?;
+    Ok(connector.connect(server_name, stream).await?)
+}
+
+enum Handshake<T> {
+    Upgraded(T),
+    Redirect(Uri),
+}
+
 /// Performs a WebSocket handshake on an existing TCP connection via HTTP 1.
-async fn handshake<T>(mut stream: T, uri: &Uri) -> ConnectResult<T>
+async fn handshake<T>(
+    mut stream: T,
+    request: &Request<()>,
+    cookie_jar: Option<&CookieJar>,
+    follow_redirects: bool,
+) -> ConnectResult<Handshake<T>>
 where
     T: AsyncReadRent + AsyncWriteRent,
 {
@@ -93,10 +227,12 @@ where
     let key = BASE64_STANDARD.encode(key_bytes);
 
     // Create the HTTP request for the handshake.
-    let request = http_request(uri, &key);
+    let uri = request.uri();
+    let cookie = cookie_jar.and_then(|cookie_jar| cookie_jar.cookie_header(uri));
+    let request = http_request(request, &key, cookie.as_deref());
 
     // Send the handshake request.
-    let (result, _) = stream.write_all(request.into_bytes()).await;
+    let (result, _) = stream.write_all(request).await;
     result?;
 
     // Read the response.
@@ -110,6 +246,28 @@ where
         }
     }
 
+    // Capture cookies set by the server, including on redirects.
+    if let Some(cookie_jar) = cookie_jar {
+        for set_cookie in headers(&response, "Set-Cookie") {
+            cookie_jar.store(uri, set_cookie);
+        }
+    }
+
+    // Follow redirects to the location given by the server.
+    if follow_redirects
+        && matches!(
+            response.split(' ').nth(1),
+            Some("301" | "302" | "307" | "308")
+        )
+    {
+        let Some(location) =
+            header(&response, "Location").and_then(|location| redirect_uri(uri, location))
+        else {
+            return Err(ConnectError::InvalidRedirectLocation(response));
+        };
+        return Ok(Handshake::Redirect(location));
+    }
+
     // Verify the response status.
     if !response.starts_with("HTTP/1.1 101") {
         return Err(ConnectError::InvalidHandshakeResponse(response));
@@ -128,28 +286,134 @@ where
         return Err(ConnectError::InvalidWebSocketAcceptHeader);
     }
 
-    Ok(stream)
+    Ok(Handshake::Upgraded(stream))
+}
+
+/// Returns the value of the first header in `response` matching `name`.
+fn header<'a>(response: &'a str, name: &str) -> Option<&'a str> {
+    headers(response, name).next()
 }
 
-fn http_request(uri: &Uri, key: &str) -> String {
+/// Returns the values of all headers in `response` matching `name`.
+fn headers<'a>(response: &'a str, name: &str) -> impl Iterator<Item = &'a str> {
+    response
+        .split("\r\n")
+        .skip(1)
+        .filter_map(|line| line.split_once(':'))
+        .filter(move |(key, _)| key.trim().eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.trim())
+}
+
+/// Resolves a redirect `location` against the URI which was redirected,
+/// mapping HTTP schemes onto their WebSocket counterparts. Redirects from a
+/// secure to an insecure URI are rejected.
+fn redirect_uri(uri: &Uri, location: &str) -> Option<Uri> {
+    let target = if let Some(rest) = location.strip_prefix("https://") {
+        format!("wss://{rest}")
+    } else if let Some(rest) = location.strip_prefix("http://") {
+        format!("ws://{rest}")
+    } else if location.starts_with("//") {
+        format!("{}:{location}", uri.scheme_str()?)
+    } else if location.starts_with('/') {
+        format!("{}://{}{location}", uri.scheme_str()?, uri.authority()?)
+    } else {
+        location.to_owned()
+    };
+    let target = target.parse::<Uri>().ok()?;
+    match (uri.scheme_str(), target.scheme_str()) {
+        (Some("wss"), Some("ws")) => None,
+        (_, Some("ws" | "wss")) => Some(target),
+        _ => None,
+    }
+}
+
+fn http_request(request: &Request<()>, key: &str, cookie: Option<&str>) -> Vec<u8> {
+    let uri = request.uri();
+    let path = match uri.path() {
+        "" => "/",
+        path => path,
+    };
+    let target = match uri.query() {
+        Some(query) => format!("{path}?{query}"),
+        None => path.to_owned(),
+    };
     let host = if let Some(port) = uri.port_u16() {
         format!("{}:{port}", uri.host().unwrap_or_default())
     } else {
         uri.host().unwrap_or_default().to_string()
     };
 
-    format!(
-        "GET {} HTTP/1.1\r\n\
-         Host: {host}\r\n\
-         Upgrade: websocket\r\n\
-         Connection: Upgrade\r\n\
-         Sec-WebSocket-Key: {key}\r\n\
-         Sec-WebSocket-Version: 13\r\n\
-         \r\n",
-        uri.path_and_query()
-            .map(ToString::to_string)
-            .unwrap_or_default(),
-    )
+    let mut dst = Vec::with_capacity(512);
+    dst.extend_from_slice(format!("{} {target} HTTP/1.1\r\n", request.method()).as_bytes());
+    write_header(
+        &mut dst,
+        &HOST,
+        request
+            .headers()
+            .get(HOST)
+            .map_or(host.as_bytes(), HeaderValue::as_bytes),
+    );
+    write_header(&mut dst, &UPGRADE, b"websocket");
+    write_header(&mut dst, &CONNECTION, b"Upgrade");
+    write_header(&mut dst, &SEC_WEBSOCKET_KEY, key.as_bytes());
+    write_header(&mut dst, &SEC_WEBSOCKET_VERSION, b"13");
+    // Headers written by the library rather than copied from the request.
+    let managed = [
+        HOST,
+        UPGRADE,
+        CONNECTION,
+        SEC_WEBSOCKET_KEY,
+        SEC_WEBSOCKET_VERSION,
+        COOKIE,
+    ];
+    for (name, value) in request.headers() {
+        if !managed.contains(name) {
+            write_header(&mut dst, name, value.as_bytes());
+        }
+    }
+
+    // Merge cookies given in the request with the ones from the cookie jar.
+    let cookies = request
+        .headers()
+        .get_all(COOKIE)
+        .iter()
+        .map(HeaderValue::as_bytes)
+        .chain(cookie.map(str::as_bytes))
+        .collect::<Vec<_>>();
+    if !cookies.is_empty() {
+        write_header(&mut dst, &COOKIE, &cookies.join(&b"; "[..]));
+    }
+
+    dst.extend_from_slice(b"\r\n");
+    dst
+}
+
+fn write_header(dst: &mut Vec<u8>, name: &HeaderName, value: &[u8]) {
+    dst.extend_from_slice(header_case(name).as_bytes());
+    dst.extend_from_slice(b": ");
+    dst.extend_from_slice(value);
+    dst.extend_from_slice(b"\r\n");
+}
+
+/// Returns the conventional capitalization of the header name, as `http`
+/// stores them in lowercase. Some servers are picky about it.
+fn header_case(name: &HeaderName) -> String {
+    match name.as_str() {
+        "sec-websocket-key" => "Sec-WebSocket-Key".to_owned(),
+        "sec-websocket-version" => "Sec-WebSocket-Version".to_owned(),
+        "sec-websocket-protocol" => "Sec-WebSocket-Protocol".to_owned(),
+        "sec-websocket-extensions" => "Sec-WebSocket-Extensions".to_owned(),
+        name => name
+            .split('-')
+            .map(|part| {
+                let mut chars = part.chars();
+                chars.next().map_or_else(String::new, |first| {
+                    first.to_ascii_uppercase().to_string() + chars.as_str()
+                })
+            })
+            .collect::<Vec<_>>()
+            .join("-"),
+    }
 }
 
 async fn read_line<T>(stream: &mut T) -> io::Result<String>
@@ -176,16 +440,23 @@ where
 
 #[cfg(test)]
 mod tests {
+    use std::str;
+
+    use test_case::test_case;
+
     use super::*;
 
     #[test]
     fn test_http_request() {
         let output = http_request(
-            &Uri::from_static("ws://localhost:9001/runCase?case=1&agent=monoio-ws"),
+            &upgrade_request(&Uri::from_static(
+                "ws://localhost:9001/runCase?case=1&agent=monoio-ws",
+            )),
             "dGhlIHNhbXBsZSBub25jZQ==",
+            None,
         );
         assert_eq!(
-            output,
+            str::from_utf8(&output).unwrap(),
             "GET /runCase?case=1&agent=monoio-ws HTTP/1.1\r\n\
             Host: localhost:9001\r\n\
             Upgrade: websocket\r\n\
@@ -195,4 +466,105 @@ mod tests {
             \r\n"
         )
     }
+
+    #[test]
+    fn test_http_request_without_path() {
+        let output = http_request(
+            &upgrade_request(&Uri::from_static("wss://example.com")),
+            "dGhlIHNhbXBsZSBub25jZQ==",
+            None,
+        );
+        assert!(
+            str::from_utf8(&output)
+                .unwrap()
+                .starts_with("GET / HTTP/1.1\r\n")
+        );
+    }
+
+    #[test]
+    fn test_http_request_with_headers() {
+        let request = Request::builder()
+            .uri("wss://example.com/feed")
+            .header(HOST, "gateway.example.com")
+            .header("sec-websocket-protocol", "v2.feed")
+            .header("x-api-key", "secret")
+            .header(COOKIE, "token=xyz")
+            .body(())
+            .unwrap();
+        let output = http_request(
+            &request,
+            "dGhlIHNhbXBsZSBub25jZQ==",
+            Some("session=abc; venue=x"),
+        );
+        assert_eq!(
+            str::from_utf8(&output).unwrap(),
+            "GET /feed HTTP/1.1\r\n\
+            Host: gateway.example.com\r\n\
+            Upgrade: websocket\r\n\
+            Connection: Upgrade\r\n\
+            Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+            Sec-WebSocket-Version: 13\r\n\
+            Sec-WebSocket-Protocol: v2.feed\r\n\
+            X-Api-Key: secret\r\n\
+            Cookie: token=xyz; session=abc; venue=x\r\n\
+            \r\n"
+        )
+    }
+
+    #[test_case("wss://eu.example.com/feed", "wss://eu.example.com/feed"; "absolute")]
+    #[test_case("https://eu.example.com/feed", "wss://eu.example.com/feed"; "https")]
+    #[test_case("http://eu.example.com/feed", "ws://eu.example.com/feed"; "http")]
+    #[test_case("//eu.example.com/feed", "ws://eu.example.com/feed"; "scheme relative")]
+    #[test_case("/v2/feed?a=b", "ws://example.com:8080/v2/feed?a=b"; "path absolute")]
+    fn test_redirect_uri(location: &str, expected: &str) {
+        let uri = Uri::from_static("ws://example.com:8080/feed");
+        assert_eq!(
+            redirect_uri(&uri, location),
+            Some(expected.parse().unwrap())
+        );
+    }
+
+    #[test_case("ws://example.com/feed", "ftp://example.com/feed"; "unknown scheme")]
+    #[test_case("wss://example.com/feed", "ws://example.com/feed"; "downgrade")]
+    #[test_case("wss://example.com/feed", "http://example.com/feed"; "downgrade to http")]
+    fn test_rejected_redirect_uri(uri: &str, location: &str) {
+        assert_eq!(redirect_uri(&uri.parse().unwrap(), location), None);
+    }
+
+    #[test_case("wss://example.com:443/v2", true; "same origin")]
+    #[test_case("wss://eu.example.com/feed", false; "other host")]
+    #[test_case("ws://example.com/feed", false; "other scheme")]
+    #[test_case("wss://example.com:8443/feed", false; "other port")]
+    fn test_redirect_request(location: &str, keeps_credentials: bool) {
+        let mut request = Request::builder()
+            .uri("wss://example.com/feed")
+            .header(HOST, "example.com")
+            .header(SEC_WEBSOCKET_PROTOCOL, "v2.feed")
+            .header("authorization", "Bearer secret")
+            .header("x-api-key", "secret")
+            .header(COOKIE, "token=xyz")
+            .body(())
+            .unwrap();
+        redirect_request(&mut request, location.parse().unwrap());
+
+        assert_eq!(request.uri(), location);
+        let headers = request.headers();
+        assert!(!headers.contains_key(HOST));
+        assert!(headers.contains_key(SEC_WEBSOCKET_PROTOCOL));
+        for name in ["authorization", "x-api-key", "cookie"] {
+            assert_eq!(headers.contains_key(name), keeps_credentials, "{name}");
+        }
+    }
+
+    #[test]
+    fn test_header() {
+        let response = "HTTP/1.1 301 Moved Permanently\r\n\
+                        location:  wss://eu.example.com/feed \r\n\
+                        \r\n";
+        assert_eq!(
+            header(response, "Location"),
+            Some("wss://eu.example.com/feed")
+        );
+        assert_eq!(header(response, "Set-Cookie"), None);
+    }
 }
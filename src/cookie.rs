@@ -0,0 +1,491 @@
+use std::{
+    cell::RefCell,
+    time::{Duration, Instant, SystemTime},
+};
+
+use http::Uri;
+
+/// Captures cookies set by servers in handshake responses and sends them back
+/// in the `Cookie` header of later handshakes to matching URIs.
+///
+/// Cookies are kept in memory only. Expiry is taken from the `Max-Age`
+/// attribute, or the `Expires` attribute in its absence.
+#[derive(Debug, Default)]
+pub struct CookieJar {
+    cookies: RefCell<Vec<Cookie>>,
+}
+
+#[derive(Debug, Clone)]
+struct Cookie {
+    name: String,
+    value: String,
+    domain: String,
+    host_only: bool,
+    path: String,
+    secure: bool,
+    expires: Option<Instant>,
+}
+
+impl CookieJar {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stores the cookie from a `Set-Cookie` header value received in response
+    /// to a request to `uri`. Invalid cookies are ignored.
+    pub fn store(&self, uri: &Uri, set_cookie: &str) {
+        let Some(cookie) = Cookie::parse(uri, set_cookie) else {
+            return;
+        };
+        let mut cookies = self.cookies.borrow_mut();
+        cookies.retain(|existing| {
+            existing.name != cookie.name
+                || existing.domain != cookie.domain
+                || existing.path != cookie.path
+        });
+        if !cookie.is_expired(Instant::now()) {
+            cookies.push(cookie);
+        }
+    }
+
+    /// Returns the value of the `Cookie` header to send in a request to `uri`,
+    /// if any cookies match it.
+    #[must_use]
+    pub fn cookie_header(&self, uri: &Uri) -> Option<String> {
+        let now = Instant::now();
+        let mut cookies = self.cookies.borrow_mut();
+        cookies.retain(|cookie| !cookie.is_expired(now));
+
+        let header = cookies
+            .iter()
+            .filter(|cookie| cookie.matches(uri))
+            .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+            .collect::<Vec<_>>()
+            .join("; ");
+        (!header.is_empty()).then_some(header)
+    }
+
+    pub fn clear(&self) {
+        self.cookies.borrow_mut().clear();
+    }
+}
+
+impl Cookie {
+    fn parse(uri: &Uri, set_cookie: &str) -> Option<Self> {
+        let host = uri.host()?.to_ascii_lowercase();
+        let secure_scheme = is_secure(uri);
+        let mut attributes = set_cookie.split(';');
+
+        let (name, value) = attributes.next()?.split_once('=')?;
+        let (name, value) = (name.trim(), value.trim());
+        // Cookies are sent back verbatim, so anything that could break out of
+        // the `Cookie` header, such as control characters, is rejected.
+        if !is_cookie_name(name) || !is_cookie_value(value) {
+            return None;
+        }
+
+        let mut cookie = Self {
+            name: name.to_owned(),
+            value: value.to_owned(),
+            domain: host.clone(),
+            host_only: true,
+            path: default_path(uri.path()).to_owned(),
+            secure: false,
+            expires: None,
+        };
+
+        let mut expires = None;
+        let mut max_age = None;
+        for attribute in attributes {
+            let (key, value) = attribute
+                .split_once('=')
+                .map_or((attribute.trim(), ""), |(key, value)| {
+                    (key.trim(), value.trim())
+                });
+            if key.eq_ignore_ascii_case("Domain") {
+                let domain = value.trim_start_matches('.').to_ascii_lowercase();
+                if domain.is_empty() {
+                    continue;
+                }
+                // Servers may only set cookies for their own domain.
+                if !domain_matches(&host, &domain) {
+                    return None;
+                }
+                // Lacking a public suffix list, at least reject top-level
+                // domains, unless they name the host itself.
+                if !domain.contains('.') {
+                    if domain == host {
+                        continue;
+                    }
+                    return None;
+                }
+                cookie.domain = domain;
+                cookie.host_only = false;
+            } else if key.eq_ignore_ascii_case("Path") {
+                if value.starts_with('/') {
+                    value.clone_into(&mut cookie.path);
+                }
+            } else if key.eq_ignore_ascii_case("Secure") {
+                // Only secure connections may set secure cookies.
+                if !secure_scheme {
+                    return None;
+                }
+                cookie.secure = true;
+            } else if key.eq_ignore_ascii_case("Max-Age") {
+                if let Ok(seconds) = value.parse::<i64>() {
+                    // Non-positive values expire the cookie immediately.
+                    max_age = Some(Duration::from_secs(
+                        u64::try_from(seconds).unwrap_or_default(),
+                    ));
+                }
+            } else if key.eq_ignore_ascii_case("Expires")
+                && let Some(date) = parse_cookie_date(value)
+            {
+                // A date in the past yields a zero duration, expiring the
+                // cookie immediately.
+                expires = Some(date.duration_since(SystemTime::now()).unwrap_or_default());
+            }
+        }
+        // Max-Age takes precedence over Expires. Expiry too far in the future
+        // to be represented is treated as no expiry at all.
+        cookie.expires = max_age
+            .or(expires)
+            .and_then(|ttl| Instant::now().checked_add(ttl));
+
+        Some(cookie)
+    }
+
+    fn is_expired(&self, now: Instant) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    fn matches(&self, uri: &Uri) -> bool {
+        let Some(host) = uri.host().map(str::to_ascii_lowercase) else {
+            return false;
+        };
+        let domain_ok = if self.host_only {
+            host == self.domain
+        } else {
+            domain_matches(&host, &self.domain)
+        };
+        let secure_ok = !self.secure || is_secure(uri);
+        domain_ok && secure_ok && path_matches(uri.path(), &self.path)
+    }
+}
+
+/// Whether `name` is a token as defined by RFC 6265, section 4.1.1.
+fn is_cookie_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|byte| byte.is_ascii_graphic() && !b"()<>@,;:\\\"/[]?={}".contains(&byte))
+}
+
+/// Whether `value` consists of cookie-octets as defined by RFC 6265, section
+/// 4.1.1, optionally enclosed in double quotes.
+fn is_cookie_value(value: &str) -> bool {
+    let value = value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value);
+    value
+        .bytes()
+        .all(|byte| byte.is_ascii_graphic() && !b"\",;\\".contains(&byte))
+}
+
+fn is_secure(uri: &Uri) -> bool {
+    matches!(uri.scheme_str(), Some("wss" | "https"))
+}
+
+/// Domain matching as defined in RFC 6265:
+/// <https://datatracker.ietf.org/doc/html/rfc6265#section-5.1.3>
+fn domain_matches(host: &str, domain: &str) -> bool {
+    host == domain
+        || host
+            .strip_suffix(domain)
+            .is_some_and(|prefix| prefix.ends_with('.'))
+}
+
+/// Path matching as defined in RFC 6265:
+/// <https://datatracker.ietf.org/doc/html/rfc6265#section-5.1.4>
+fn path_matches(request_path: &str, cookie_path: &str) -> bool {
+    let request_path = if request_path.is_empty() {
+        "/"
+    } else {
+        request_path
+    };
+    request_path
+        .strip_prefix(cookie_path)
+        .is_some_and(|rest| rest.is_empty() || cookie_path.ends_with('/') || rest.starts_with('/'))
+}
+
+/// Default cookie path as defined in RFC 6265:
+/// <https://datatracker.ietf.org/doc/html/rfc6265#section-5.1.4>
+fn default_path(request_path: &str) -> &str {
+    match request_path.rfind('/') {
+        Some(0) | None => "/",
+        Some(index) => &request_path[..index],
+    }
+}
+
+/// Parses the value of an `Expires` attribute as defined in RFC 6265:
+/// <https://datatracker.ietf.org/doc/html/rfc6265#section-5.1.1>
+fn parse_cookie_date(date: &str) -> Option<SystemTime> {
+    const MONTHS: [&str; 12] = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+
+    let mut time = None;
+    let mut day = None;
+    let mut month = None;
+    let mut year = None;
+
+    let is_delimiter = |c: char| matches!(c, '\t' | ' '..='/' | ';'..='@' | '['..='`' | '{'..='~');
+    for token in date.split(is_delimiter).filter(|token| !token.is_empty()) {
+        if time.is_none()
+            && let Some(parsed) = parse_time(token)
+        {
+            time = Some(parsed);
+        } else if day.is_none()
+            && let Some(parsed) = parse_digits(token, 1, 2)
+        {
+            day = Some(parsed);
+        } else if month.is_none()
+            && let Some(index) = token.get(..3).and_then(|prefix| {
+                MONTHS
+                    .iter()
+                    .position(|month| prefix.eq_ignore_ascii_case(month))
+            })
+        {
+            month = Some(index as i64 + 1);
+        } else if year.is_none()
+            && let Some(parsed) = parse_digits(token, 2, 4)
+        {
+            year = Some(parsed);
+        }
+    }
+
+    let (hour, minute, second) = time?;
+    let (day, month, mut year) = (day?, month?, year?);
+    match year {
+        70..=99 => year += 1900,
+        0..=69 => year += 2000,
+        _ => {}
+    }
+    if !(1..=31).contains(&day) || year < 1601 || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+
+    let seconds = days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second;
+    // Dates before the epoch are just as expired as the epoch itself.
+    Some(SystemTime::UNIX_EPOCH + Duration::from_secs(u64::try_from(seconds).unwrap_or_default()))
+}
+
+/// Parses a token starting with `min` to `max` digits.
+fn parse_digits(token: &str, min: usize, max: usize) -> Option<i64> {
+    let len = token.bytes().take_while(u8::is_ascii_digit).count();
+    (min..=max)
+        .contains(&len)
+        .then(|| token[..len].parse().ok())
+        .flatten()
+}
+
+/// Parses a token starting with a time in the form of `hh:mm:ss`, where each
+/// field may consist of one or two digits.
+fn parse_time(token: &str) -> Option<(i64, i64, i64)> {
+    let mut fields = token.splitn(3, ':');
+    let hour = fields.next()?;
+    let minute = fields.next()?;
+    let second = fields.next()?;
+    // Only the last field may be followed by anything else.
+    if hour.len() > 2 || minute.len() > 2 {
+        return None;
+    }
+    Some((
+        parse_digits(hour, 1, 2)?,
+        parse_digits(minute, 1, 2)?,
+        parse_digits(second, 1, 2)?,
+    ))
+}
+
+/// Days since the Unix epoch of a date in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::*;
+
+    #[test_case("ws://example.com/feed", Some("session=abc"); "same host")]
+    #[test_case("ws://example.com/other", Some("session=abc"); "other path")]
+    #[test_case("ws://eu.example.com/feed", None; "subdomain of host only cookie")]
+    #[test_case("ws://example.org/feed", None; "other host")]
+    fn test_host_only_cookie(uri: &str, expected: Option<&str>) {
+        let jar = CookieJar::new();
+        jar.store(&Uri::from_static("ws://example.com/feed"), "session=abc");
+        assert_eq!(
+            jar.cookie_header(&uri.parse().unwrap()).as_deref(),
+            expected
+        );
+    }
+
+    #[test_case("ws://example.com/", Some("session=abc"); "parent domain")]
+    #[test_case("ws://eu.example.com/", Some("session=abc"); "subdomain")]
+    #[test_case("ws://badexample.com/", None; "suffix without dot")]
+    fn test_domain_cookie(uri: &str, expected: Option<&str>) {
+        let jar = CookieJar::new();
+        jar.store(
+            &Uri::from_static("ws://example.com/"),
+            "session=abc; Domain=.example.com",
+        );
+        assert_eq!(
+            jar.cookie_header(&uri.parse().unwrap()).as_deref(),
+            expected
+        );
+    }
+
+    #[test_case("session=abc; Domain=example.org"; "foreign domain")]
+    #[test_case("session=abc; Domain=com"; "top level domain")]
+    #[test_case("session=abc; Domain=.com"; "top level domain with dot")]
+    #[test_case("session=abc; Secure"; "secure over plain connection")]
+    #[test_case("=abc"; "empty name")]
+    #[test_case("ses sion=abc"; "space in name")]
+    #[test_case("session=a\nX-Injected: 1"; "newline in value")]
+    #[test_case("session=a\rb"; "carriage return in value")]
+    #[test_case("session=a\x7Fb"; "delete in value")]
+    #[test_case("session=a b"; "space in value")]
+    #[test_case("session=a\\b"; "backslash in value")]
+    #[test_case("session=\"abc"; "unbalanced quote")]
+    fn test_rejected_cookie(set_cookie: &str) {
+        let jar = CookieJar::new();
+        let uri = Uri::from_static("ws://example.com/");
+        jar.store(&uri, set_cookie);
+        assert_eq!(jar.cookie_header(&uri), None);
+    }
+
+    #[test]
+    fn test_single_label_host() {
+        let jar = CookieJar::new();
+        let uri = Uri::from_static("ws://localhost/");
+        jar.store(&uri, "session=abc; Domain=localhost");
+        assert_eq!(jar.cookie_header(&uri).as_deref(), Some("session=abc"));
+    }
+
+    #[test_case("Sun, 06 Nov 1994 08:49:37 GMT"; "rfc 1123")]
+    #[test_case("Sunday, 06-Nov-94 08:49:37 GMT"; "rfc 850")]
+    #[test_case("Sun Nov  6 08:49:37 1994"; "asctime")]
+    fn test_parse_cookie_date(date: &str) {
+        assert_eq!(
+            parse_cookie_date(date),
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(784_111_777))
+        );
+    }
+
+    #[test_case("Sun, 06 Nov 1994 GMT"; "missing time")]
+    #[test_case("Sun, 32 Nov 1994 08:49:37 GMT"; "invalid day")]
+    #[test_case("Sun, 06 Foo 1994 08:49:37 GMT"; "invalid month")]
+    #[test_case("Sun, 06 Nov 1994 24:00:00 GMT"; "invalid hour")]
+    fn test_parse_invalid_cookie_date(date: &str) {
+        assert_eq!(parse_cookie_date(date), None);
+    }
+
+    #[test_case("/api", "/api", true; "identical")]
+    #[test_case("/api/feed", "/api", true; "sub path")]
+    #[test_case("/api/feed", "/api/", true; "sub path of trailing slash")]
+    #[test_case("/apifeed", "/api", false; "prefix without slash")]
+    #[test_case("/", "/api", false; "parent path")]
+    #[test_case("", "/", true; "empty path")]
+    fn test_path_matches(request_path: &str, cookie_path: &str, expected: bool) {
+        assert_eq!(path_matches(request_path, cookie_path), expected);
+    }
+
+    #[test_case("", "/"; "empty")]
+    #[test_case("/", "/"; "root")]
+    #[test_case("/feed", "/"; "top level")]
+    #[test_case("/api/v1/feed", "/api/v1"; "nested")]
+    fn test_default_path(request_path: &str, expected: &str) {
+        assert_eq!(default_path(request_path), expected);
+    }
+
+    #[test]
+    fn test_secure_cookie() {
+        let jar = CookieJar::new();
+        jar.store(
+            &Uri::from_static("wss://example.com/"),
+            "session=abc; Secure",
+        );
+        assert_eq!(
+            jar.cookie_header(&Uri::from_static("wss://example.com/"))
+                .as_deref(),
+            Some("session=abc")
+        );
+        assert_eq!(
+            jar.cookie_header(&Uri::from_static("ws://example.com/")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_replace_and_remove() {
+        let jar = CookieJar::new();
+        let uri = Uri::from_static("ws://example.com/");
+        jar.store(&uri, "session=abc");
+        jar.store(&uri, "venue=x");
+        jar.store(&uri, "session=def");
+        assert_eq!(
+            jar.cookie_header(&uri).as_deref(),
+            Some("venue=x; session=def")
+        );
+        jar.store(&uri, "venue=; Max-Age=0");
+        assert_eq!(jar.cookie_header(&uri).as_deref(), Some("session=def"));
+        jar.store(&uri, "session=; Expires=Thu, 01 Jan 1970 00:00:00 GMT");
+        assert_eq!(jar.cookie_header(&uri), None);
+    }
+
+    #[test]
+    fn test_max_age_takes_precedence() {
+        let jar = CookieJar::new();
+        let uri = Uri::from_static("ws://example.com/");
+        jar.store(
+            &uri,
+            "session=abc; Max-Age=3600; Expires=Thu, 01 Jan 1970 00:00:00 GMT",
+        );
+        assert_eq!(jar.cookie_header(&uri).as_deref(), Some("session=abc"));
+    }
+
+    #[test]
+    fn test_quoted_value() {
+        let jar = CookieJar::new();
+        let uri = Uri::from_static("ws://example.com/");
+        jar.store(&uri, "session=\"abc\"");
+        assert_eq!(jar.cookie_header(&uri).as_deref(), Some("session=\"abc\""));
+    }
+
+    #[test]
+    fn test_huge_max_age() {
+        let jar = CookieJar::new();
+        let uri = Uri::from_static("ws://example.com/");
+        jar.store(&uri, "session=abc; Max-Age=9223372036854775807");
+        assert_eq!(jar.cookie_header(&uri).as_deref(), Some("session=abc"));
+    }
+
+    #[test]
+    fn test_invalid_cookie_keeps_others() {
+        let jar = CookieJar::new();
+        let uri = Uri::from_static("ws://example.com/");
+        jar.store(&uri, "session=abc");
+        jar.store(&uri, "venue=x\x01y");
+        let header = jar.cookie_header(&uri).unwrap();
+        assert_eq!(header, "session=abc");
+        assert!(http::HeaderValue::try_from(header).is_ok());
+    }
+}
@@ -0,0 +1,17 @@
+use crate::CloseFrame;
+
+/// Something received from the peer by
+/// [`Client::next_event`](crate::Client::next_event). The payload of
+/// messages, pings and pongs is returned in the buffer passed in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    Text,
+    Binary,
+    /// A ping, which has already been replied to as configured by
+    /// [`Config::pong_policy`](crate::Config::pong_policy).
+    Ping,
+    Pong,
+    /// The peer has closed the connection, with `None` for a close frame
+    /// without a close code.
+    Close(Option<CloseFrame>),
+}
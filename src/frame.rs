@@ -7,13 +7,13 @@ const CONTROL_HEADER_LEN: usize = 6;
 const MAX_HEADER_LEN: usize = 14;
 const MASK_BIT: u8 = 0x80;
 
-#[derive(Clone, Copy, Debug)]
-pub enum Message {
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MessageKind {
     Binary,
     Text,
 }
 
-impl Message {
+impl MessageKind {
     #[must_use]
     pub fn is_binary(self) -> bool {
         matches!(self, Self::Binary)
@@ -174,6 +174,51 @@ impl<'a> Frame<'a> {
     pub fn validate_utf8(data: &[u8]) -> Option<&str> {
         simdutf8::basic::from_utf8(data).ok()
     }
+
+    /// Copies the payload into a frame which can be kept across reads.
+    #[must_use]
+    pub fn into_owned(self) -> OwnedFrame {
+        OwnedFrame {
+            fin: self.fin,
+            opcode: self.opcode,
+            data: self.data.to_vec(),
+        }
+    }
+}
+
+/// A frame owning its payload. Unlike a received [`Frame`], which borrows the
+/// client's read buffer, it can be kept while reading the next frame or moved
+/// to another task.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OwnedFrame {
+    pub fin: bool,
+    pub opcode: Opcode,
+    pub data: Vec<u8>,
+}
+
+impl OwnedFrame {
+    /// Borrows the frame, for example to send it with
+    /// [`Client::write_frame`](crate::Client::write_frame).
+    #[must_use]
+    pub fn as_frame(&self) -> Frame<'_> {
+        Frame {
+            fin: self.fin,
+            opcode: self.opcode,
+            data: &self.data,
+        }
+    }
+}
+
+impl From<Frame<'_>> for OwnedFrame {
+    fn from(frame: Frame<'_>) -> Self {
+        frame.into_owned()
+    }
+}
+
+impl<'a> From<&'a OwnedFrame> for Frame<'a> {
+    fn from(frame: &'a OwnedFrame) -> Self {
+        frame.as_frame()
+    }
 }
 
 unsafe fn mask_data(src: *const u8, dst: *mut u8, len: usize, mask: [u8; 4]) {
@@ -455,4 +500,22 @@ mod tests {
     fn test_invalid_utf8(input: &[u8]) {
         assert_eq!(Frame::validate_utf8(input), None);
     }
+
+    #[test]
+    fn test_owned_round_trip() {
+        let data = vec![1, 2, 3];
+        let owned = Frame {
+            fin: false,
+            opcode: Opcode::Continuation,
+            data: &data,
+        }
+        .into_owned();
+        drop(data);
+
+        let frame = owned.as_frame();
+        assert!(!frame.fin);
+        assert_eq!(frame.opcode, Opcode::Continuation);
+        assert_eq!(frame.data, [1, 2, 3]);
+        assert_eq!(OwnedFrame::from(frame), owned);
+    }
 }
@@ -0,0 +1,294 @@
+use std::{future::poll_fn, io, ptr, slice};
+
+use bytes::{Buf, Bytes};
+use h2::{RecvStream, SendStream, client::SendRequest, ext::Protocol};
+use http::{
+    HeaderValue, Method, Request, Uri,
+    header::{COOKIE, SEC_WEBSOCKET_VERSION, SET_COOKIE},
+    uri::{PathAndQuery, Scheme},
+};
+use monoio::{
+    BufResult,
+    buf::{IoBuf, IoBufMut, IoVecBuf, IoVecBufMut},
+    io::{AsyncReadRent, AsyncWriteRent, Split},
+};
+use monoio_compat::StreamWrapper;
+
+use crate::{
+    Client, Config, ConnectError, ConnectResult,
+    connect::{tcp_connect, tls_connect, upgrade_request},
+};
+
+/// An HTTP/2 connection over which WebSockets are bootstrapped with the
+/// extended CONNECT method as defined in RFC 8441:
+/// <https://datatracker.ietf.org/doc/html/rfc8441>
+///
+/// Each WebSocket opened on the connection occupies its own HTTP/2 stream,
+/// allowing many of them to share a single TCP or TLS connection. Cloning the
+/// connection is cheap and refers to the same underlying connection.
+#[derive(Clone)]
+pub struct H2Connection {
+    send_request: SendRequest<Bytes>,
+}
+
+impl H2Connection {
+    /// Connects to the authority of a `ws` URI via cleartext HTTP/2 with prior
+    /// knowledge, or of a `wss` URI via HTTP/2 negotiated with ALPN. Fails with
+    /// [`ConnectError::Http2NotNegotiated`] if the server doesn't agree to it.
+    pub async fn connect(uri: &Uri) -> ConnectResult<Self> {
+        match uri.scheme_str() {
+            Some("ws") => Self::handshake(tcp_connect(uri, 80).await?).await,
+            Some("wss") => {
+                let stream = tls_connect(uri, vec![b"h2".to_vec()]).await?;
+                if stream.alpn_protocol().as_deref() != Some(b"h2") {
+                    return Err(ConnectError::Http2NotNegotiated);
+                }
+                Self::handshake(stream).await
+            }
+            _ => Err(ConnectError::InvalidUriScheme),
+        }
+    }
+
+    /// Performs the HTTP/2 handshake on an existing stream. The connection is
+    /// driven by a task spawned on the current runtime.
+    pub async fn handshake<T>(stream: T) -> ConnectResult<Self>
+    where
+        T: AsyncReadRent + AsyncWriteRent + Unpin + 'static,
+    {
+        let (send_request, connection) = h2::client::handshake(StreamWrapper::new(stream)).await?;
+        monoio::spawn(async move {
+            let _ = connection.await;
+        });
+        Ok(Self { send_request })
+    }
+
+    /// Opens a WebSocket to `uri` on a new HTTP/2 stream.
+    pub async fn open(&self, uri: &Uri, config: &Config) -> ConnectResult<Client<H2Stream>> {
+        self.open_request(upgrade_request(uri), config).await
+    }
+
+    /// Like [`H2Connection::open`], but takes the URI and any additional
+    /// headers of the extended CONNECT request from `request`.
+    pub async fn open_request(
+        &self,
+        request: Request<()>,
+        config: &Config,
+    ) -> ConnectResult<Client<H2Stream>> {
+        let (mut parts, ()) = request.into_parts();
+        let uri = parts.uri.clone();
+
+        // Extended CONNECT requests carry the HTTP rather than the WebSocket
+        // scheme.
+        let mut uri_parts = uri.clone().into_parts();
+        uri_parts.scheme = Some(match uri.scheme_str() {
+            Some("ws") => Scheme::HTTP,
+            Some("wss") => Scheme::HTTPS,
+            _ => return Err(ConnectError::InvalidUriScheme),
+        });
+        if uri_parts.path_and_query.is_none() {
+            uri_parts.path_and_query = Some(PathAndQuery::from_static("/"));
+        }
+        parts.uri = Uri::from_parts(uri_parts).map_err(|_| ConnectError::InvalidUriScheme)?;
+        parts.method = Method::CONNECT;
+        parts.extensions.insert(Protocol::from_static("websocket"));
+        parts
+            .headers
+            .insert(SEC_WEBSOCKET_VERSION, HeaderValue::from_static("13"));
+        if let Some(cookie) = config
+            .cookie_jar
+            .as_deref()
+            .and_then(|cookie_jar| cookie_jar.cookie_header(&uri))
+            .and_then(|cookie| HeaderValue::try_from(cookie).ok())
+        {
+            parts.headers.append(COOKIE, cookie);
+        }
+
+        let mut send_request = self.send_request.clone().ready().await?;
+        let (response, send) = send_request.send_request(Request::from_parts(parts, ()), false)?;
+        let response = response.await?;
+
+        if let Some(cookie_jar) = config.cookie_jar.as_deref() {
+            for set_cookie in response.headers().get_all(SET_COOKIE) {
+                if let Ok(set_cookie) = set_cookie.to_str() {
+                    cookie_jar.store(&uri, set_cookie);
+                }
+            }
+        }
+
+        if !response.status().is_success() {
+            return Err(ConnectError::InvalidHandshakeResponse(
+                response.status().to_string(),
+            ));
+        }
+
+        let stream = H2Stream {
+            send,
+            recv: response.into_body(),
+            pending: Bytes::new(),
+        };
+        Ok(Client::new(stream, config))
+    }
+}
+
+/// A single HTTP/2 stream carrying a WebSocket connection.
+///
+/// Vectored reads and writes are not supported.
+pub struct H2Stream {
+    send: SendStream<Bytes>,
+    recv: RecvStream,
+    pending: Bytes,
+}
+
+impl AsyncReadRent for H2Stream {
+    async fn read<T: IoBufMut>(&mut self, mut buf: T) -> BufResult<usize, T> {
+        // Data frames may be empty, so wait for one with a payload.
+        while self.pending.is_empty() {
+            match self.recv.data().await {
+                Some(Ok(data)) => {
+                    if let Err(e) = self.recv.flow_control().release_capacity(data.len()) {
+                        return (Err(io::Error::other(e)), buf);
+                    }
+                    self.pending = data;
+                }
+                Some(Err(e)) => return (Err(io::Error::other(e)), buf),
+                None => return (Ok(0), buf),
+            }
+        }
+
+        let len = buf.bytes_total().min(self.pending.len());
+        unsafe {
+            ptr::copy_nonoverlapping(self.pending.as_ptr(), buf.write_ptr(), len);
+            buf.set_init(len);
+        }
+        self.pending.advance(len);
+        (Ok(len), buf)
+    }
+
+    async fn readv<T: IoVecBufMut>(&mut self, buf: T) -> BufResult<usize, T> {
+        (Err(io::ErrorKind::Unsupported.into()), buf)
+    }
+}
+
+impl AsyncWriteRent for H2Stream {
+    async fn write<T: IoBuf>(&mut self, buf: T) -> BufResult<usize, T> {
+        let len = buf.bytes_init();
+        if len == 0 {
+            return (Ok(0), buf);
+        }
+
+        // Wait for the peer's flow control window to allow sending.
+        self.send.reserve_capacity(len);
+        let capacity = loop {
+            match poll_fn(|cx| self.send.poll_capacity(cx)).await {
+                Some(Ok(0)) => {}
+                Some(Ok(capacity)) => break capacity,
+                Some(Err(e)) => return (Err(io::Error::other(e)), buf),
+                None => return (Err(io::ErrorKind::BrokenPipe.into()), buf),
+            }
+        };
+
+        let len = len.min(capacity);
+        let data = Bytes::copy_from_slice(unsafe { slice::from_raw_parts(buf.read_ptr(), len) });
+        if let Err(e) = self.send.send_data(data, false) {
+            return (Err(io::Error::other(e)), buf);
+        }
+        (Ok(len), buf)
+    }
+
+    async fn writev<T: IoVecBuf>(&mut self, buf_vec: T) -> BufResult<usize, T> {
+        (Err(io::ErrorKind::Unsupported.into()), buf_vec)
+    }
+
+    async fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    async fn shutdown(&mut self) -> io::Result<()> {
+        self.send
+            .send_data(Bytes::new(), true)
+            .map_err(io::Error::other)
+    }
+}
+
+// Reads only touch the receiving and writes only the sending side of the stream.
+unsafe impl Split for H2Stream {}
+
+#[cfg(test)]
+mod tests {
+    use monoio::net::TcpListener;
+
+    use super::*;
+
+    /// Accepts a single HTTP/2 connection and echoes the unfragmented text
+    /// frames received on each of its extended CONNECT streams.
+    async fn serve(listener: TcpListener) {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut connection = h2::server::Builder::new()
+            .enable_connect_protocol()
+            .handshake::<_, Bytes>(StreamWrapper::new(stream))
+            .await
+            .unwrap();
+
+        while let Some(request) = connection.accept().await {
+            let (request, mut respond) = request.unwrap();
+            assert_eq!(request.method(), Method::CONNECT);
+            assert_eq!(
+                request.extensions().get::<Protocol>().map(Protocol::as_str),
+                Some("websocket")
+            );
+
+            let mut send = respond
+                .send_response(http::Response::new(()), false)
+                .unwrap();
+            let mut recv = request.into_body();
+            monoio::spawn(async move {
+                let mut received = Vec::new();
+                while let Some(data) = recv.data().await {
+                    let data = data.unwrap();
+                    let _ = recv.flow_control().release_capacity(data.len());
+                    received.extend_from_slice(&data);
+
+                    // Client frames consist of a 2 byte header, a 4 byte mask
+                    // and the masked payload.
+                    while received.len() >= 6
+                        && received.len() >= 6 + usize::from(received[1] & 0x7F)
+                    {
+                        let len = received[1] & 0x7F;
+                        let mask = [received[2], received[3], received[4], received[5]];
+                        let mut frame = vec![received[0], len];
+                        frame.extend(
+                            received[6..6 + usize::from(len)]
+                                .iter()
+                                .enumerate()
+                                .map(|(i, byte)| byte ^ mask[i & 3]),
+                        );
+                        received.drain(..6 + usize::from(len));
+                        send.send_data(Bytes::from(frame), false).unwrap();
+                    }
+                }
+            });
+        }
+    }
+
+    #[monoio::test]
+    async fn test_extended_connect() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let uri = format!("ws://{}/feed", listener.local_addr().unwrap())
+            .parse::<Uri>()
+            .unwrap();
+        monoio::spawn(serve(listener));
+
+        let connection = H2Connection::connect(&uri).await.unwrap();
+        let config = Config::default();
+        let mut first = connection.open(&uri, &config).await.unwrap();
+        let mut second = connection.open(&uri, &config).await.unwrap();
+
+        first.send_text("hello").await.unwrap();
+        second.send_text("world").await.unwrap();
+
+        let frame = second.read_frame().await.unwrap();
+        assert_eq!(frame.data, b"world");
+        let frame = first.read_frame().await.unwrap();
+        assert_eq!(frame.data, b"hello");
+    }
+}
@@ -1,8 +1,29 @@
 mod client;
 mod close_code;
+mod close_frame;
 mod connect;
+mod cookie;
+mod event;
 mod frame;
+#[cfg(feature = "h2")]
+mod http2;
 mod io;
+mod message;
+mod message_reader;
+mod message_writer;
 mod opcode;
+mod policy;
+mod protocol_violation;
+mod rtt;
+mod stream;
+#[cfg(test)]
+mod test_util;
+mod utf8;
 
-pub use self::{client::*, close_code::*, connect::*, frame::*, opcode::*};
+#[cfg(feature = "h2")]
+pub use self::http2::*;
+pub use self::{
+    client::*, close_code::*, close_frame::*, connect::*, cookie::*, event::*, frame::*,
+    message::*, message_reader::*, message_writer::*, opcode::*, policy::*, protocol_violation::*,
+    rtt::RttStats, stream::*,
+};
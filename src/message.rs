@@ -0,0 +1,135 @@
+use crate::MessageKind;
+
+/// A complete message with an owned payload, received by [`Client::recv`]
+/// and sent by [`Client::send`].
+///
+/// [`Client::recv`]: crate::Client::recv
+/// [`Client::send`]: crate::Client::send
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+impl Message {
+    #[must_use]
+    pub fn kind(&self) -> MessageKind {
+        match self {
+            Self::Text(_) => MessageKind::Text,
+            Self::Binary(_) => MessageKind::Binary,
+        }
+    }
+
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Self::Text(text) => text.as_bytes(),
+            Self::Binary(data) => data,
+        }
+    }
+
+    #[must_use]
+    pub fn into_bytes(self) -> Vec<u8> {
+        match self {
+            Self::Text(text) => text.into_bytes(),
+            Self::Binary(data) => data,
+        }
+    }
+}
+
+impl From<String> for Message {
+    fn from(text: String) -> Self {
+        Self::Text(text)
+    }
+}
+
+impl From<&str> for Message {
+    fn from(text: &str) -> Self {
+        Self::Text(text.to_owned())
+    }
+}
+
+impl From<Vec<u8>> for Message {
+    fn from(data: Vec<u8>) -> Self {
+        Self::Binary(data)
+    }
+}
+
+impl From<&[u8]> for Message {
+    fn from(data: &[u8]) -> Self {
+        Self::Binary(data.to_vec())
+    }
+}
+
+/// A complete message borrowing its payload from the client, received by
+/// [`Client::recv_ref`](crate::Client::recv_ref).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageRef<'a> {
+    Text(&'a str),
+    Binary(&'a [u8]),
+}
+
+impl<'a> MessageRef<'a> {
+    #[must_use]
+    pub fn kind(&self) -> MessageKind {
+        match self {
+            Self::Text(_) => MessageKind::Text,
+            Self::Binary(_) => MessageKind::Binary,
+        }
+    }
+
+    #[must_use]
+    pub fn data(&self) -> &'a [u8] {
+        match self {
+            Self::Text(text) => text.as_bytes(),
+            Self::Binary(data) => data,
+        }
+    }
+
+    /// Returns the payload of a text message.
+    #[must_use]
+    pub fn as_text(&self) -> Option<&'a str> {
+        match self {
+            Self::Text(text) => Some(text),
+            Self::Binary(_) => None,
+        }
+    }
+
+    /// Copies the payload into an owned message.
+    #[must_use]
+    pub fn to_message(&self) -> Message {
+        match self {
+            Self::Text(text) => Message::Text((*text).to_owned()),
+            Self::Binary(data) => Message::Binary(data.to_vec()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::*;
+
+    #[test_case(Message::from("héllo"), MessageKind::Text, "héllo".as_bytes(); "text")]
+    #[test_case(Message::from(&[0xFF, 0x00][..]), MessageKind::Binary, &[0xFF, 0x00]; "binary")]
+    fn test_payload(message: Message, kind: MessageKind, bytes: &[u8]) {
+        assert_eq!(message.kind(), kind);
+        assert_eq!(message.as_bytes(), bytes);
+        assert_eq!(message.into_bytes(), bytes);
+    }
+
+    #[test_case(MessageRef::Text("hi"), Some("hi"); "text")]
+    #[test_case(MessageRef::Binary(b"hi"), None; "binary")]
+    fn test_as_text(message: MessageRef<'_>, expected: Option<&str>) {
+        assert_eq!(message.as_text(), expected);
+        assert_eq!(message.data(), b"hi");
+    }
+
+    #[test_case(MessageRef::Text("hi"), Message::from("hi"); "text")]
+    #[test_case(MessageRef::Binary(b"hi"), Message::from(&b"hi"[..]); "binary")]
+    fn test_to_message(message: MessageRef<'_>, expected: Message) {
+        assert_eq!(message.to_message(), expected);
+        assert_eq!(message.kind(), expected.kind());
+    }
+}
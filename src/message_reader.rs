@@ -0,0 +1,137 @@
+use monoio::io::{AsyncReadRent, AsyncWriteRent, AsyncWriteRentExt};
+
+use crate::{Client, MessageKind, Result};
+
+/// Part of a received message's payload, handed out as soon as it arrives.
+#[derive(Clone, Copy, Debug)]
+pub struct Chunk<'a> {
+    pub kind: MessageKind,
+    /// Whether this is the last chunk of the message.
+    pub fin: bool,
+    pub data: &'a [u8],
+}
+
+/// Receives a message in chunks as they arrive, created by
+/// [`Client::message_reader`]. A chunk is at most a fragment of the message,
+/// but large fragments are split into several chunks.
+///
+/// Text messages are validated as UTF-8 chunk by chunk, so a chunk may end
+/// within a code point. A message left unfinished by a dropped reader is
+/// continued by the next one, while receiving it otherwise, such as with
+/// [`Client::next_msg`], fails with
+/// [`Error::MessageInProgress`](crate::Error::MessageInProgress) until then.
+/// Likewise, a reader can't continue a message partially received otherwise.
+pub struct MessageReader<'a, S>
+where
+    S: AsyncWriteRent,
+{
+    client: &'a mut Client<S>,
+    finished: bool,
+}
+
+impl<'a, S> MessageReader<'a, S>
+where
+    S: AsyncReadRent + AsyncWriteRent + 'static,
+{
+    pub(crate) fn new(client: &'a mut Client<S>) -> Self {
+        Self {
+            client,
+            finished: false,
+        }
+    }
+
+    /// Returns the next chunk of the message, or `None` once the final one has
+    /// been returned.
+    pub async fn next_chunk(&mut self) -> Result<Option<Chunk<'_>>> {
+        if self.finished {
+            return Ok(None);
+        }
+        let chunk = self.client.next_chunk().await?;
+        self.finished = chunk.fin;
+        Ok(Some(chunk))
+    }
+
+    /// Writes the rest of the message to `sink` as it arrives, returning the
+    /// number of bytes written.
+    pub async fn pipe_to<W>(mut self, sink: &mut W) -> Result<u64>
+    where
+        W: AsyncWriteRent,
+    {
+        let mut total = 0;
+        let mut buffer = Vec::new();
+        while let Some(chunk) = self.next_chunk().await? {
+            buffer.clear();
+            buffer.extend_from_slice(chunk.data);
+            total += buffer.len() as u64;
+            let (res, buf) = sink.write_all(buffer).await;
+            buffer = buf;
+            res?;
+        }
+        Ok(total)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Config, Error, Message, test_util::serve};
+
+    #[monoio::test(timer_enabled = true)]
+    async fn test_chunks() {
+        // A fragment, an interleaved ping and a final fragment which arrives
+        // in two parts.
+        let mut client = serve(
+            &[b"\x01\x03hel\x89\x00\x80\x04lo", b"!!"],
+            &Config::default(),
+        )
+        .await;
+        let mut reader = client.message_reader();
+        let mut chunks = Vec::new();
+        while let Some(chunk) = reader.next_chunk().await.unwrap() {
+            assert!(chunk.kind.is_text());
+            chunks.push((chunk.fin, chunk.data.to_vec()));
+        }
+
+        assert_eq!(
+            chunks,
+            [
+                (false, b"hel".to_vec()),
+                (false, b"lo".to_vec()),
+                (true, b"!!".to_vec()),
+            ]
+        );
+    }
+
+    #[monoio::test(timer_enabled = true)]
+    async fn test_unlimited_size() {
+        let config = Config {
+            max_frame_size: Some(2),
+            max_message_size: Some(2),
+            ..Config::default()
+        };
+        // A message in two fragments, each larger than the limits.
+        let mut client = serve(&[b"\x02\x03abc\x80\x03def"], &config).await;
+        let mut reader = client.message_reader();
+        let mut data = Vec::new();
+        while let Some(chunk) = reader.next_chunk().await.unwrap() {
+            data.extend_from_slice(chunk.data);
+        }
+        assert_eq!(data, b"abcdef");
+    }
+
+    #[monoio::test(timer_enabled = true)]
+    async fn test_dropped_reader() {
+        // A message in two fragments followed by another one.
+        let mut client = serve(&[b"\x01\x03hel\x80\x02lo\x82\x01!"], &Config::default()).await;
+
+        let mut reader = client.message_reader();
+        let chunk = reader.next_chunk().await.unwrap().unwrap();
+        assert_eq!(chunk.data, b"hel");
+        // The reader is abandoned with the message unfinished.
+        assert!(matches!(client.recv().await, Err(Error::MessageInProgress)));
+
+        let mut reader = client.message_reader();
+        let chunk = reader.next_chunk().await.unwrap().unwrap();
+        assert_eq!((chunk.fin, chunk.data), (true, &b"lo"[..]));
+        assert_eq!(client.recv().await.unwrap(), Message::Binary(b"!".to_vec()));
+    }
+}
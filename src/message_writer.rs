@@ -0,0 +1,147 @@
+use std::mem;
+
+use monoio::io::{AsyncReadRent, AsyncWriteRent};
+
+use crate::{Client, Error, Frame, MessageKind, Opcode, Result, io::AsyncReadRentExt as _};
+
+/// Sends a message in fragments of arbitrary size, created by
+/// [`Client::message_writer`].
+///
+/// The first fragment carries the message's opcode and the following ones
+/// [`Opcode::Continuation`]. Control frames may be sent in between. Until the
+/// message is finished, no other data frames may be sent on the client. A
+/// message left unfinished by a dropped writer is continued by the next one
+/// of the same kind, while writers of the other kind fail with
+/// [`Error::OutOfSequenceFrame`].
+pub struct MessageWriter<'a, S>
+where
+    S: AsyncWriteRent,
+{
+    client: &'a mut Client<S>,
+    kind: MessageKind,
+}
+
+impl<'a, S> MessageWriter<'a, S>
+where
+    S: AsyncReadRent + AsyncWriteRent + 'static,
+{
+    pub(crate) fn new(client: &'a mut Client<S>, kind: MessageKind) -> Self {
+        Self { client, kind }
+    }
+
+    /// Sends `data` as the next fragment of the message.
+    pub async fn write(&mut self, data: &[u8]) -> Result<()> {
+        self.write_fragment(false, data).await
+    }
+
+    /// Sends `data` as the final fragment of the message.
+    pub async fn finish(mut self, data: &[u8]) -> Result<()> {
+        self.write_fragment(true, data).await
+    }
+
+    /// Sends the contents of `reader` until its end, in fragments of up to
+    /// `chunk_size` bytes, and finishes the message. Returns the message
+    /// length.
+    ///
+    /// One chunk is read ahead, so that the final fragment carries data unless
+    /// the message is empty.
+    pub async fn copy_from<R>(mut self, reader: &mut R, chunk_size: usize) -> Result<u64>
+    where
+        R: AsyncReadRent,
+    {
+        let mut total = 0;
+        let mut current = fill(reader, Vec::with_capacity(chunk_size), chunk_size).await?;
+        let mut next = Vec::with_capacity(chunk_size);
+        loop {
+            next.clear();
+            next = fill(reader, next, chunk_size).await?;
+            total += current.len() as u64;
+            if next.is_empty() {
+                self.finish(&current).await?;
+                return Ok(total);
+            }
+            self.write(&current).await?;
+            mem::swap(&mut current, &mut next);
+        }
+    }
+
+    /// Sends a ping in between fragments of the message.
+    pub async fn send_ping(&mut self, data: &[u8]) -> Result<()> {
+        self.client.send_ping(data).await
+    }
+
+    /// Sends a pong in between fragments of the message.
+    pub async fn send_pong(&mut self, data: &[u8]) -> Result<()> {
+        self.client.send_pong(data).await
+    }
+
+    async fn write_fragment(&mut self, fin: bool, data: &[u8]) -> Result<()> {
+        // Taken from the client rather than tracked here, so that a message
+        // left unfinished by a dropped writer is continued by the next one.
+        let opcode = match (self.client.sending_message(), self.kind) {
+            (None, MessageKind::Binary) => Opcode::Binary,
+            (None, MessageKind::Text) => Opcode::Text,
+            (Some(kind), _) if kind == self.kind => Opcode::Continuation,
+            // A message of the other kind is being sent.
+            (Some(_), _) => return Err(Error::OutOfSequenceFrame),
+        };
+        self.client.write_frame(Frame { fin, opcode, data }).await
+    }
+}
+
+/// Reads from `reader` until `buffer` holds `len` bytes or the end is reached.
+async fn fill<R>(reader: &mut R, mut buffer: Vec<u8>, len: usize) -> Result<Vec<u8>>
+where
+    R: AsyncReadRent,
+{
+    while buffer.len() < len {
+        let remaining = len - buffer.len();
+        let (res, buf) = reader.read_extend(buffer, remaining).await;
+        buffer = buf;
+        if res? == 0 {
+            break;
+        }
+    }
+    Ok(buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, test_util::receive};
+
+    #[monoio::test]
+    async fn test_fragments() {
+        let (mut client, server) = receive(4, &Config::default()).await;
+        let mut writer = client.message_writer(MessageKind::Text);
+        writer.write(b"he").await.unwrap();
+        writer.send_ping(b"").await.unwrap();
+        writer.write(b"ll").await.unwrap();
+        assert!(matches!(
+            client.send_binary(b"interleaved").await,
+            Err(Error::OutOfSequenceFrame)
+        ));
+        assert!(matches!(
+            client
+                .message_writer(MessageKind::Binary)
+                .finish(b"o")
+                .await,
+            Err(Error::OutOfSequenceFrame)
+        ));
+        client
+            .message_writer(MessageKind::Text)
+            .finish(b"o")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            server.await,
+            [
+                (0x01, b"he".to_vec()),
+                (0x89, Vec::new()),
+                (0x00, b"ll".to_vec()),
+                (0x80, b"o".to_vec()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,38 @@
+use std::rc::Rc;
+
+use crate::CloseFrame;
+
+/// Returns the payload of the pong replying to a ping with the given payload.
+pub type PongCallback = Rc<dyn Fn(&[u8]) -> Option<Vec<u8>>>;
+
+/// Returns the close frame replying to a received one.
+pub type CloseCallback = Rc<dyn Fn(Option<&CloseFrame>) -> Option<CloseFrame>>;
+
+/// How received pings are replied to.
+#[derive(Clone, Default)]
+pub enum PongPolicy {
+    /// Replies with a pong carrying the ping's payload.
+    #[default]
+    Automatic,
+    /// Never replies. The application is responsible for sending pongs with
+    /// [`Client::send_pong`](crate::Client::send_pong).
+    Manual,
+    /// Replies with the payload returned for the ping's payload, truncated to
+    /// 125 bytes, or not at all if `None` is returned.
+    Callback(PongCallback),
+}
+
+/// How a close frame initiated by the peer is replied to.
+#[derive(Clone, Default)]
+pub enum ClosePolicy {
+    /// Echoes the received close code and shuts down the stream.
+    #[default]
+    Automatic,
+    /// Never replies. The application is responsible for completing the
+    /// closing handshake with [`Client::close`](crate::Client::close) or
+    /// [`Client::send_close`](crate::Client::send_close).
+    Manual,
+    /// Replies with the close frame returned for the received one and shuts
+    /// down the stream. Returning `None` replies without a close code.
+    Callback(CloseCallback),
+}
@@ -0,0 +1,89 @@
+use crate::{CloseCode, CloseFrameError};
+
+/// Ways in which the peer may violate RFC 6455 or the limits set in
+/// [`Config`](crate::Config), each of which fails the connection with a close
+/// frame carrying [`ProtocolViolation::close_code`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum ProtocolViolation {
+    #[error("Reserve bit must be 0.")]
+    ReservedBits,
+    #[error("Use of reserved opcode.")]
+    ReservedOpcode,
+    #[error("Server to client communication should be unmasked.")]
+    MaskedFrame,
+    #[error("Control frame larger than 125 bytes.")]
+    ControlFrameTooLarge,
+    #[error("Control frame cannot be fragmented.")]
+    FragmentedControlFrame,
+    #[error("Received continuation frame without preceding text or binary frame.")]
+    UnexpectedContinuation,
+    #[error("Received a new data frame while expecting a continuation frame.")]
+    ExpectedContinuation,
+    #[error("Received invalid utf-8.")]
+    InvalidUtf8,
+    #[error("Payload length with the most significant bit set.")]
+    InvalidPayloadLength,
+    #[error("Close frame with a missing close code byte.")]
+    InvalidClosePayload,
+    #[error("Invalid close code.")]
+    InvalidCloseCode,
+    #[error("Message exceeds the maximum size.")]
+    MessageTooBig,
+    #[error("Ping rate limit exceeded.")]
+    PingRateExceeded,
+    #[error("Message split into too many fragments.")]
+    TooManyFragments,
+    #[error("Fragment smaller than the minimum size.")]
+    FragmentTooSmall,
+}
+
+impl ProtocolViolation {
+    /// Close code sent to the peer when failing the connection:
+    /// <https://datatracker.ietf.org/doc/html/rfc6455#section-7.4.1>
+    #[must_use]
+    pub fn close_code(self) -> CloseCode {
+        match self {
+            Self::InvalidUtf8 => CloseCode::InvalidFramePayloadData,
+            Self::MessageTooBig => CloseCode::MessageTooBig,
+            Self::PingRateExceeded | Self::TooManyFragments | Self::FragmentTooSmall => {
+                CloseCode::PolicyViolation
+            }
+            _ => CloseCode::ProtocolError,
+        }
+    }
+}
+
+impl From<CloseFrameError> for ProtocolViolation {
+    fn from(e: CloseFrameError) -> Self {
+        match e {
+            CloseFrameError::MissingCodeByte => Self::InvalidClosePayload,
+            CloseFrameError::InvalidCode(_) | CloseFrameError::ReservedCode(_) => {
+                Self::InvalidCloseCode
+            }
+            CloseFrameError::InvalidUtf8 => Self::InvalidUtf8,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::*;
+
+    #[test_case(ProtocolViolation::ReservedBits, CloseCode::ProtocolError; "reserved bits")]
+    #[test_case(ProtocolViolation::InvalidCloseCode, CloseCode::ProtocolError; "invalid close code")]
+    #[test_case(ProtocolViolation::InvalidUtf8, CloseCode::InvalidFramePayloadData; "invalid utf8")]
+    #[test_case(ProtocolViolation::MessageTooBig, CloseCode::MessageTooBig; "message too big")]
+    #[test_case(ProtocolViolation::TooManyFragments, CloseCode::PolicyViolation; "too many fragments")]
+    fn test_close_code(violation: ProtocolViolation, expected: CloseCode) {
+        assert_eq!(violation.close_code(), expected);
+    }
+
+    #[test_case(CloseFrameError::MissingCodeByte, ProtocolViolation::InvalidClosePayload; "missing code byte")]
+    #[test_case(CloseFrameError::ReservedCode(CloseCode::Abnormal), ProtocolViolation::InvalidCloseCode; "reserved code")]
+    #[test_case(CloseFrameError::InvalidUtf8, ProtocolViolation::InvalidUtf8; "invalid utf8 reason")]
+    fn test_from_close_frame_error(e: CloseFrameError, expected: ProtocolViolation) {
+        assert_eq!(ProtocolViolation::from(e), expected);
+    }
+}
@@ -0,0 +1,182 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// Round-trip time statistics gathered from pongs replying to pings sent with
+/// [`Client::send_rtt_ping`](crate::Client::send_rtt_ping).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RttStats {
+    latest: Option<Duration>,
+    min: Option<Duration>,
+    smoothed: Option<Duration>,
+    samples: u64,
+}
+
+impl RttStats {
+    /// Round-trip time of the most recently answered ping.
+    #[must_use]
+    pub fn latest(&self) -> Option<Duration> {
+        self.latest
+    }
+
+    /// Lowest round-trip time measured so far.
+    #[must_use]
+    pub fn min(&self) -> Option<Duration> {
+        self.min
+    }
+
+    /// Exponentially weighted moving average of the round-trip time, weighting
+    /// each new sample by 1/8 as in RFC 6298.
+    #[must_use]
+    pub fn smoothed(&self) -> Option<Duration> {
+        self.smoothed
+    }
+
+    /// Number of round-trip times measured so far.
+    #[must_use]
+    pub fn samples(&self) -> u64 {
+        self.samples
+    }
+
+    fn update(&mut self, rtt: Duration) {
+        self.latest = Some(rtt);
+        self.min = Some(self.min.map_or(rtt, |min| min.min(rtt)));
+        self.smoothed = Some(
+            self.smoothed
+                .map_or(rtt, |smoothed| (smoothed * 7 + rtt) / 8),
+        );
+        self.samples += 1;
+    }
+}
+
+/// Encodes a sequence number and the time of sending into the payload of RTT
+/// pings and measures the round-trip time once the matching pong arrives.
+pub(crate) struct RttTracker {
+    epoch: Instant,
+    next_sequence: u64,
+    /// Sequence numbers of the pings not answered yet, oldest first.
+    outstanding: VecDeque<u64>,
+    stats: RttStats,
+}
+
+impl RttTracker {
+    const PAYLOAD_LEN: usize = 16;
+    /// Pings left unanswered beyond this many are forgotten, oldest first.
+    const MAX_OUTSTANDING: usize = 64;
+
+    pub(crate) fn new() -> Self {
+        Self {
+            epoch: Instant::now(),
+            next_sequence: 0,
+            outstanding: VecDeque::new(),
+            stats: RttStats::default(),
+        }
+    }
+
+    pub(crate) fn stats(&self) -> RttStats {
+        self.stats
+    }
+
+    /// Returns the payload of a new RTT ping sent at `now`.
+    pub(crate) fn ping_payload(&mut self, now: Instant) -> [u8; Self::PAYLOAD_LEN] {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        if self.outstanding.len() == Self::MAX_OUTSTANDING {
+            self.outstanding.pop_front();
+        }
+        self.outstanding.push_back(sequence);
+        let micros = u64::try_from(now.duration_since(self.epoch).as_micros()).unwrap_or(u64::MAX);
+
+        let mut payload = [0; Self::PAYLOAD_LEN];
+        payload[..8].copy_from_slice(&sequence.to_be_bytes());
+        payload[8..].copy_from_slice(&micros.to_be_bytes());
+        payload
+    }
+
+    /// Measures the round-trip time if `data` is the payload of a pong replying
+    /// to an outstanding RTT ping. Other pongs, including repeated replies to
+    /// the same ping, are ignored.
+    pub(crate) fn on_pong(&mut self, data: &[u8], now: Instant) {
+        let Ok(payload) = <[u8; Self::PAYLOAD_LEN]>::try_from(data) else {
+            return;
+        };
+        let sequence = u64::from_be_bytes(payload[..8].try_into().unwrap());
+        let micros = u64::from_be_bytes(payload[8..].try_into().unwrap());
+        let Some(index) = self.outstanding.iter().position(|&s| s == sequence) else {
+            return;
+        };
+        self.outstanding.remove(index);
+        let sent = self.epoch + Duration::from_micros(micros);
+        if let Some(rtt) = now.checked_duration_since(sent) {
+            self.stats.update(rtt);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut tracker = RttTracker::new();
+        let sent = tracker.epoch + Duration::from_millis(100);
+        let payload = tracker.ping_payload(sent);
+
+        tracker.on_pong(&payload, sent + Duration::from_millis(40));
+        let stats = tracker.stats();
+        assert_eq!(stats.latest(), Some(Duration::from_millis(40)));
+        assert_eq!(stats.min(), Some(Duration::from_millis(40)));
+        assert_eq!(stats.smoothed(), Some(Duration::from_millis(40)));
+        assert_eq!(stats.samples(), 1);
+    }
+
+    #[test]
+    fn test_smoothing() {
+        let mut stats = RttStats::default();
+        stats.update(Duration::from_millis(80));
+        stats.update(Duration::from_millis(160));
+        assert_eq!(stats.latest(), Some(Duration::from_millis(160)));
+        assert_eq!(stats.min(), Some(Duration::from_millis(80)));
+        assert_eq!(stats.smoothed(), Some(Duration::from_millis(90)));
+        assert_eq!(stats.samples(), 2);
+    }
+
+    #[test]
+    fn test_unrelated_pongs_are_ignored() {
+        let mut tracker = RttTracker::new();
+        let now = Instant::now();
+        tracker.on_pong(b"keepalive", now);
+        // Sequence number of a ping which has not been sent.
+        tracker.on_pong(&[0; RttTracker::PAYLOAD_LEN], now);
+        assert_eq!(tracker.stats(), RttStats::default());
+    }
+
+    #[test]
+    fn test_replayed_pongs_are_ignored() {
+        let mut tracker = RttTracker::new();
+        let sent = tracker.epoch + Duration::from_millis(100);
+        let first = tracker.ping_payload(sent);
+        let second = tracker.ping_payload(sent);
+
+        tracker.on_pong(&second, sent + Duration::from_millis(40));
+        tracker.on_pong(&second, sent + Duration::from_millis(80));
+        assert_eq!(tracker.stats().samples(), 1);
+        tracker.on_pong(&first, sent + Duration::from_millis(60));
+        assert_eq!(tracker.stats().samples(), 2);
+        assert_eq!(tracker.stats().latest(), Some(Duration::from_millis(60)));
+    }
+
+    #[test]
+    fn test_oldest_outstanding_ping_is_forgotten() {
+        let mut tracker = RttTracker::new();
+        let now = Instant::now();
+        let first = tracker.ping_payload(now);
+        for _ in 0..RttTracker::MAX_OUTSTANDING {
+            tracker.ping_payload(now);
+        }
+        tracker.on_pong(&first, now);
+        assert_eq!(tracker.stats().samples(), 0);
+    }
+}
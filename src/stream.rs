@@ -0,0 +1,73 @@
+use monoio::{
+    BufResult,
+    buf::{IoBuf, IoBufMut, IoVecBuf, IoVecBufMut},
+    io::{AsyncReadRent, AsyncWriteRent, Split},
+    net::TcpStream,
+};
+use monoio_rustls::ClientTlsStream;
+
+/// A TCP stream which may or may not be wrapped in TLS, depending on whether it
+/// was opened for a `ws` or a `wss` URI.
+// There is a single stream per connection, so boxing the TLS variant would only
+// add an indirection to every read and write.
+#[expect(clippy::large_enum_variant)]
+pub enum MaybeTlsStream {
+    Plain(TcpStream),
+    Tls(ClientTlsStream<TcpStream>),
+}
+
+impl MaybeTlsStream {
+    #[must_use]
+    pub fn is_tls(&self) -> bool {
+        matches!(self, Self::Tls(_))
+    }
+}
+
+impl AsyncReadRent for MaybeTlsStream {
+    async fn read<T: IoBufMut>(&mut self, buf: T) -> BufResult<usize, T> {
+        match self {
+            Self::Plain(stream) => stream.read(buf).await,
+            Self::Tls(stream) => stream.read(buf).await,
+        }
+    }
+
+    async fn readv<T: IoVecBufMut>(&mut self, buf: T) -> BufResult<usize, T> {
+        match self {
+            Self::Plain(stream) => stream.readv(buf).await,
+            Self::Tls(stream) => stream.readv(buf).await,
+        }
+    }
+}
+
+impl AsyncWriteRent for MaybeTlsStream {
+    async fn write<T: IoBuf>(&mut self, buf: T) -> BufResult<usize, T> {
+        match self {
+            Self::Plain(stream) => stream.write(buf).await,
+            Self::Tls(stream) => stream.write(buf).await,
+        }
+    }
+
+    async fn writev<T: IoVecBuf>(&mut self, buf_vec: T) -> BufResult<usize, T> {
+        match self {
+            Self::Plain(stream) => stream.writev(buf_vec).await,
+            Self::Tls(stream) => stream.writev(buf_vec).await,
+        }
+    }
+
+    async fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Plain(stream) => stream.flush().await,
+            Self::Tls(stream) => stream.flush().await,
+        }
+    }
+
+    async fn shutdown(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Plain(stream) => stream.shutdown().await,
+            Self::Tls(stream) => stream.shutdown().await,
+        }
+    }
+}
+
+// Both variants are safe to split into read and write halves.
+unsafe impl Split for MaybeTlsStream {}
@@ -0,0 +1,117 @@
+use std::time::Duration;
+
+use monoio::{
+    io::{AsyncReadRentExt, AsyncWriteRentExt},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+
+use crate::{Client, Config};
+
+/// Connects a client to a server which sends `parts` with a pause in between,
+/// so that each arrives in a read of its own, and then keeps the connection
+/// open for a while without reading from it.
+pub(crate) async fn serve(parts: &[&[u8]], config: &Config) -> Client<TcpStream> {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let parts: Vec<Vec<u8>> = parts.iter().map(|part| part.to_vec()).collect();
+    monoio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        write_parts(&mut stream, parts).await;
+        monoio::time::sleep(Duration::from_secs(1)).await;
+    });
+
+    let stream = TcpStream::connect(addr).await.unwrap();
+    Client::new(stream, config)
+}
+
+/// Connects a client to a server which sends `parts` like [`serve`], then reads
+/// `count` frames, returning their first header byte and unmasked payload, and
+/// finally closes the connection.
+pub(crate) async fn exchange(
+    parts: &[&[u8]],
+    count: usize,
+    config: &Config,
+) -> (Client<TcpStream>, JoinHandle<Vec<(u8, Vec<u8>)>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let parts: Vec<Vec<u8>> = parts.iter().map(|part| part.to_vec()).collect();
+    let server = monoio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        write_parts(&mut stream, parts).await;
+        read_frames(&mut stream, count).await
+    });
+
+    let stream = TcpStream::connect(addr).await.unwrap();
+    (Client::new(stream, config), server)
+}
+
+/// Connects a client to a server which answers the first `count` pings with
+/// pongs echoing their payload, and then keeps the connection open for a while
+/// without answering any more.
+pub(crate) async fn answer_pings(count: usize, config: &Config) -> Client<TcpStream> {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    monoio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        for _ in 0..count {
+            let (_, payload) = read_frames(&mut stream, 1).await.remove(0);
+            let pong = [&[0x8A, payload.len() as u8], &payload[..]].concat();
+            let (res, _) = stream.write_all(pong).await;
+            res.unwrap();
+        }
+        monoio::time::sleep(Duration::from_secs(1)).await;
+    });
+
+    let stream = TcpStream::connect(addr).await.unwrap();
+    Client::new(stream, config)
+}
+
+/// Connects a client to a server which reads `count` frames, returning their
+/// first header byte and unmasked payload.
+pub(crate) async fn receive(
+    count: usize,
+    config: &Config,
+) -> (Client<TcpStream>, JoinHandle<Vec<(u8, Vec<u8>)>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = monoio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        read_frames(&mut stream, count).await
+    });
+
+    let stream = TcpStream::connect(addr).await.unwrap();
+    (Client::new(stream, config), server)
+}
+
+/// Writes `parts` with a pause in between, so that each arrives in a read of
+/// its own.
+async fn write_parts(stream: &mut TcpStream, parts: Vec<Vec<u8>>) {
+    for (i, part) in parts.into_iter().enumerate() {
+        if i > 0 {
+            monoio::time::sleep(Duration::from_millis(100)).await;
+        }
+        let (res, _) = stream.write_all(part).await;
+        res.unwrap();
+    }
+}
+
+/// Reads `count` small masked frames, returning their first header byte and
+/// unmasked payload.
+async fn read_frames(stream: &mut TcpStream, count: usize) -> Vec<(u8, Vec<u8>)> {
+    let mut frames = Vec::new();
+    for _ in 0..count {
+        let (res, header) = stream.read_exact(vec![0; 6]).await;
+        res.unwrap();
+        let len = usize::from(header[1] & 0x7F);
+        let (res, payload) = stream.read_exact(vec![0; len]).await;
+        res.unwrap();
+        let payload = payload
+            .iter()
+            .enumerate()
+            .map(|(i, byte)| byte ^ header[2 + (i & 3)])
+            .collect();
+        frames.push((header[0], payload));
+    }
+    frames
+}
@@ -0,0 +1,99 @@
+use simdutf8::compat;
+
+/// Validates UTF-8 text received in several parts, such as the fragments of a
+/// message, as each part arrives. A code point split between parts is carried
+/// over to the next one.
+#[derive(Debug, Default)]
+pub(crate) struct Utf8Validator {
+    incomplete: [u8; 4],
+    incomplete_len: usize,
+}
+
+impl Utf8Validator {
+    /// Validates the next part of the text, returning whether it is valid so
+    /// far. With `fin` set, the text must not end with an incomplete code
+    /// point.
+    #[must_use]
+    pub(crate) fn feed(&mut self, mut data: &[u8], fin: bool) -> bool {
+        if self.incomplete_len > 0 {
+            let carried = self.incomplete_len;
+            let needed = sequence_len(self.incomplete[0]) - carried;
+            let taken = needed.min(data.len());
+            self.incomplete[carried..carried + taken].copy_from_slice(&data[..taken]);
+            self.incomplete_len += taken;
+            data = &data[taken..];
+
+            match compat::from_utf8(&self.incomplete[..self.incomplete_len]) {
+                Ok(_) => self.incomplete_len = 0,
+                // All of `data` has been taken and the code point is still
+                // incomplete.
+                Err(e) if e.error_len().is_none() => return !fin,
+                Err(_) => return false,
+            }
+        }
+
+        match compat::from_utf8(data) {
+            Ok(_) => true,
+            Err(e) if e.error_len().is_none() && !fin => {
+                let rest = &data[e.valid_up_to()..];
+                self.incomplete[..rest.len()].copy_from_slice(rest);
+                self.incomplete_len = rest.len();
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Forgets any incomplete code point, to validate a new text.
+    pub(crate) fn reset(&mut self) {
+        self.incomplete_len = 0;
+    }
+}
+
+/// Length of the sequence started by `lead`, which is known to be the valid
+/// start of a multi-byte sequence.
+fn sequence_len(lead: u8) -> usize {
+    match lead {
+        0xC0..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        _ => 4,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::*;
+
+    #[test_case(&[b"Hello"]; "single part")]
+    #[test_case(&[b"Hel", b"lo"]; "ascii parts")]
+    #[test_case(&[b"\xC3", b"\xA9"]; "two-byte sequence split")]
+    #[test_case(&[b"\xF0\x9F", b"\xA6\x80"]; "four-byte sequence split in half")]
+    #[test_case(&[b"\xF0", b"\x9F", b"\xA6", b"\x80!"]; "four-byte sequence split into bytes")]
+    #[test_case(&[b"a\xE2", b"", b"\x82\xAC"]; "empty part in between")]
+    fn test_valid(parts: &[&[u8]]) {
+        let mut validator = Utf8Validator::default();
+        let (last, parts) = parts.split_last().unwrap();
+        for part in parts {
+            assert!(validator.feed(part, false));
+        }
+        assert!(validator.feed(last, true));
+    }
+
+    #[test_case(&[b"\xFF", b"valid"], 0; "invalid start byte")]
+    #[test_case(&[b"\xC3", b"a"], 1; "interrupted sequence")]
+    #[test_case(&[b"\xED\xA0", b"\x80"], 0; "surrogate")]
+    #[test_case(&[b"ok", b"\xE2\x82"], 1; "incomplete at end")]
+    #[test_case(&[b"\xF4", b"\x90\x80\x80"], 1; "beyond maximum code point split")]
+    fn test_invalid(parts: &[&[u8]], fails_at: usize) {
+        let mut validator = Utf8Validator::default();
+        for (i, part) in parts.iter().enumerate() {
+            let fin = i + 1 == parts.len();
+            assert_eq!(validator.feed(part, fin), i < fails_at, "part {i}");
+            if i == fails_at {
+                break;
+            }
+        }
+    }
+}